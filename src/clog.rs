@@ -3,27 +3,91 @@
 use std::convert::From;
 use std::error;
 use std::fmt::{Display, Formatter, Result as FmtResult};
-use std::io::{self, BufRead, Error as IoError, Write};
+use std::io::{self, Error as IoError, Write};
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::string::FromUtf8Error;
 
+use log::info;
+
+use crate::git::{self, GitCmd};
 use crate::parser::{self, Combinator, ParseError};
 
+pub mod check;
+pub mod fix;
+pub mod github;
+pub mod mklog;
+
 #[derive(Debug)]
 pub enum Error<'clog> {
+    Git(git::Error),
     Io(IoError),
     Parser(ParseError<'clog>),
     Utf8(FromUtf8Error),
+    /// GCC's ChangeLog checker could not check the commits
+    Checker(String),
+    Github(octocrab::Error),
+    MissingToken,
+    /// Commits cannot be rewritten when no branch is checked out
+    DetachedHead,
+    /// The range to fix does not end at the current commit
+    NotHead(String),
+    /// Commits cannot be rewritten over uncommitted changes
+    DirtyWorkTree,
+    /// The range to fix contains merges, which cannot be replayed
+    Merges(String),
+    /// The root commit has no parent to replay the commits on
+    RootCommit(String),
+    /// The branch on which commits are rewritten is left over from a previous run
+    BranchExists(&'static str),
 }
 
 impl<'clog> Display for Error<'clog> {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-        write!(f, "{self:#?}")
+        match self {
+            Error::Git(e) => write!(f, "{e}"),
+            Error::Io(e) => write!(f, "I/O error: {e}"),
+            Error::Parser(e) => write!(f, "parse error: {e}"),
+            Error::Utf8(e) => write!(f, "invalid UTF-8: {e}"),
+            Error::Checker(stderr) => write!(f, "ChangeLog checker failed: {stderr}"),
+            Error::Github(e) => write!(f, "GitHub error: {e}"),
+            Error::MissingToken => write!(
+                f,
+                "no github token provided (`--token-file`, `GERRIS_TOKEN`, `GITHUB_TOKEN` or \
+                git's credential helper) to comment on the pull-request"
+            ),
+            Error::DetachedHead => write!(f, "cannot rewrite commits without a branch checked out"),
+            Error::NotHead(range) => write!(
+                f,
+                "`{range}` does not end at the current commit, whose history would be rewritten"
+            ),
+            Error::DirtyWorkTree => write!(
+                f,
+                "cannot rewrite commits with uncommitted changes: commit or stash them first"
+            ),
+            Error::Merges(range) => write!(f, "cannot rewrite `{range}`, which contains merges"),
+            Error::RootCommit(hash) => {
+                write!(
+                    f,
+                    "cannot rewrite the root commit {hash}, which has no parent"
+                )
+            }
+            Error::BranchExists(branch) => write!(
+                f,
+                "branch `{branch}` already exists: delete it if it is left over from a previous run"
+            ),
+        }
     }
 }
 
 impl<'clog> error::Error for Error<'clog> {}
 
+impl<'clog> From<git::Error> for Error<'clog> {
+    fn from(e: git::Error) -> Error<'clog> {
+        Error::Git(e)
+    }
+}
+
 impl<'clog> From<IoError> for Error<'clog> {
     fn from(e: IoError) -> Error<'clog> {
         Error::Io(e)
@@ -36,26 +100,159 @@ impl<'clog> From<ParseError<'clog>> for Error<'clog> {
     }
 }
 
+impl<'clog> From<octocrab::Error> for Error<'clog> {
+    fn from(e: octocrab::Error) -> Error<'clog> {
+        Error::Github(e)
+    }
+}
+
 impl<'clog> From<FromUtf8Error> for Error<'clog> {
     fn from(e: FromUtf8Error) -> Error<'clog> {
         Error::Utf8(e)
     }
 }
 
+#[derive(Clone)]
+pub struct ChangeLogsOpt {
+    /// Python interpreter with which to run GCC's scripts
+    pub python: String,
+    /// Path to `mklog.py`, relative to the GCC repository. Skeletons are generated by gerris itself
+    /// if unset.
+    pub mklog: Option<PathBuf>,
+    /// Path to `git_check_commit.py`, relative to the GCC repository
+    pub checker: PathBuf,
+}
+
+impl Default for ChangeLogsOpt {
+    fn default() -> ChangeLogsOpt {
+        ChangeLogsOpt {
+            python: String::from("python3"),
+            mklog: None,
+            checker: PathBuf::from("contrib/gcc-changelog/git_check_commit.py"),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum Status {
     Success,
     Failed,
 }
 
-#[derive(Debug)]
-struct CheckLine {
-    hash: String,
-    status: Status,
+/// Error reported by the checker on an `ERR: ` line. The quoted file or line of the commit
+/// message is kept as is, including its trailing newline if any.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// `cannot find a ChangeLog location in message`
+    MissingLocation,
+    /// `changed file not mentioned in a ChangeLog: "<file>"`
+    FileNotMentioned(String),
+    /// `new file in the top-level folder not mentioned in a ChangeLog: "<file>"`
+    NewTopLevelFile(String),
+    /// `unchanged file mentioned in a ChangeLog: "<file>"`
+    UnchangedFileMentioned(String),
+    /// `trailing whitespace: "<line>"`
+    TrailingWhitespace(String),
+    /// `line exceeds <limit> character limit: "<line>"`
+    LineTooLong { limit: usize, line: String },
+    /// `line should start with a tab: "<line>"`
+    MissingTab(String),
+    /// `first line should start with a tab, an asterisk and a space: "<line>"`
+    MissingStar(String),
+    /// `missing PR component: "<line>"`
+    MissingPrComponent(String),
+    /// `PR <number> in subject but not in changelog`
+    PrNotInChangeLog(String),
+    /// Any other error, as printed by the checker
+    Other(String),
+}
+
+impl Diagnostic {
+    fn parse(error: &str) -> Diagnostic {
+        let (message, quoted) = match error.split_once(": \"") {
+            Some((message, rest)) if rest.ends_with('"') => {
+                (message, Some(rest[..rest.len() - 1].to_string()))
+            }
+            _ => (error, None),
+        };
+
+        if let Some(pr) = message
+            .strip_prefix("PR ")
+            .and_then(|message| message.strip_suffix(" in subject but not in changelog"))
+        {
+            return Diagnostic::PrNotInChangeLog(pr.to_string());
+        }
+
+        let limit = message
+            .strip_prefix("line exceeds ")
+            .and_then(|message| message.strip_suffix(" character limit"))
+            .and_then(|limit| limit.parse().ok());
+
+        match (message, quoted, limit) {
+            ("cannot find a ChangeLog location in message", None, _) => Diagnostic::MissingLocation,
+            ("changed file not mentioned in a ChangeLog", Some(file), _) => {
+                Diagnostic::FileNotMentioned(file)
+            }
+            ("new file in the top-level folder not mentioned in a ChangeLog", Some(file), _) => {
+                Diagnostic::NewTopLevelFile(file)
+            }
+            ("unchanged file mentioned in a ChangeLog", Some(file), _) => {
+                Diagnostic::UnchangedFileMentioned(file)
+            }
+            ("trailing whitespace", Some(line), _) => Diagnostic::TrailingWhitespace(line),
+            ("line should start with a tab", Some(line), _) => Diagnostic::MissingTab(line),
+            ("first line should start with a tab, an asterisk and a space", Some(line), _) => {
+                Diagnostic::MissingStar(line)
+            }
+            ("missing PR component", Some(line), _) => Diagnostic::MissingPrComponent(line),
+            (_, Some(line), Some(limit)) => Diagnostic::LineTooLong { limit, line },
+            _ => Diagnostic::Other(error.to_string()),
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    /// Render the diagnostic on a single line, escaping the newlines of the quoted text
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Diagnostic::MissingLocation => write!(f, "cannot find a ChangeLog location in message"),
+            Diagnostic::FileNotMentioned(file) => {
+                write!(f, "changed file not mentioned in a ChangeLog: {file:?}")
+            }
+            Diagnostic::NewTopLevelFile(file) => write!(
+                f,
+                "new file in the top-level folder not mentioned in a ChangeLog: {file:?}"
+            ),
+            Diagnostic::UnchangedFileMentioned(file) => {
+                write!(f, "unchanged file mentioned in a ChangeLog: {file:?}")
+            }
+            Diagnostic::TrailingWhitespace(line) => write!(f, "trailing whitespace: {line:?}"),
+            Diagnostic::LineTooLong { limit, line } => {
+                write!(f, "line exceeds {limit} character limit: {line:?}")
+            }
+            Diagnostic::MissingTab(line) => write!(f, "line should start with a tab: {line:?}"),
+            Diagnostic::MissingStar(line) => write!(
+                f,
+                "first line should start with a tab, an asterisk and a space: {line:?}"
+            ),
+            Diagnostic::MissingPrComponent(line) => write!(f, "missing PR component: {line:?}"),
+            Diagnostic::PrNotInChangeLog(pr) => {
+                write!(f, "PR {pr} in subject but not in changelog")
+            }
+            Diagnostic::Other(error) => write!(f, "{}", error.replace('\n', "\\n")),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct CheckLine {
+    pub hash: String,
+    pub status: Status,
+    pub diagnostics: Vec<Diagnostic>,
 }
 
 // FIXME: This can return a slice of input by using indexes
-fn hash(input: &str) -> Result<(&str, String), ParseError> {
+fn hash(input: &str) -> Result<(&str, String), ParseError<'_>> {
     let mut hash = String::new();
     let mut input = input;
 
@@ -74,7 +271,7 @@ fn hash(input: &str) -> Result<(&str, String), ParseError> {
     }
 }
 
-fn parse_checking_line(line: &str) -> Result<CheckLine, ParseError> {
+fn parse_checking_line(line: &str) -> Result<CheckLine, ParseError<'_>> {
     // FIXME: Add validation this is a proper hash and not some random string
     // FIXME: Should we check this hash belongs to the repository
 
@@ -92,49 +289,275 @@ fn parse_checking_line(line: &str) -> Result<CheckLine, ParseError> {
             "FAILED" => Status::Failed,
             _ => unreachable!(),
         },
+        diagnostics: Vec::new(),
     })
 }
 
-pub fn check_clog_checker_output<'clog>() -> Result<(), Error<'clog>> {
-    let stdin = io::stdin();
-
-    for line in stdin.lock().lines() {
-        let line = line?;
-        let line = parse_checking_line(&line);
-        if let Ok(check_line) = line {
-            if check_line.status == Status::Failed {
-                let patch = Command::new("git")
-                    .args(["show", &check_line.hash, "-1"])
-                    .output()?
-                    .stdout;
-                let mut changelog_cmd =
-                    Command::new("python3") /* FIXME: Is that correct? Probably not */
-                        // FIXME: Fix path
-                        .arg("contrib/mklog.py") /* FIXME: We should probably use a Path here */
-                        .stdin(Stdio::piped())
-                        .stdout(Stdio::piped())
-                        .spawn()?;
-
-                dbg!(&patch);
-                dbg!(&check_line);
-
-                changelog_cmd.stdin.take().unwrap().write_all(&patch)?;
-
-                let cl = changelog_cmd.wait_with_output()?.stdout;
-                println!(
-                    "* Changelog skeleton for commit {}:\n```{}```",
-                    check_line.hash,
-                    String::from_utf8(cl)?
-                );
-            }
+fn parse_error_line(line: &str) -> Result<&str, ParseError<'_>> {
+    let (line, _) = parser::tag("ERR:")(line)?;
+    let (line, _) = parser::whitespace(line)?;
+
+    Ok(line)
+}
+
+/// Whether the error quotes a line of the commit message whose closing quote is yet to come
+fn quote_open(error: &str) -> bool {
+    error
+        .split_once(": \"")
+        .is_some_and(|(_, quoted)| !quoted.ends_with('"'))
+}
+
+/// Parse the output of the checker, attaching the errors to the commit they were reported for.
+/// An error quoting a line of the commit message spans several lines of output when that line
+/// contains a newline.
+pub fn parse_checker_output(output: &str) -> Vec<CheckLine> {
+    fn flush(check_lines: &mut [CheckLine], error: Option<String>) {
+        if let (Some(check_line), Some(error)) = (check_lines.last_mut(), error) {
+            check_line.diagnostics.push(Diagnostic::parse(&error));
         }
     }
 
-    Ok(())
+    let mut check_lines: Vec<CheckLine> = Vec::new();
+    let mut error: Option<String> = None;
+
+    for line in output.lines() {
+        if let Some(pending) = error.as_mut().filter(|pending| quote_open(pending)) {
+            pending.push('\n');
+            pending.push_str(line);
+        } else if let Ok(check_line) = parse_checking_line(line) {
+            flush(&mut check_lines, error.take());
+            check_lines.push(check_line);
+        } else if let Ok(text) = parse_error_line(line) {
+            flush(&mut check_lines, error.replace(text.to_string()));
+        } else if let Some(pending) = error.as_mut() {
+            pending.push('\n');
+            pending.push_str(line);
+        }
+    }
+    flush(&mut check_lines, error);
+
+    check_lines
+}
+
+/// Run GCC's ChangeLog checker on a range of commits of `repo`, e.g. `gcc/trunk..HEAD`, and
+/// capture its output
+fn run_checker(
+    repo: &git::Repo,
+    opt: &ChangeLogsOpt,
+    range: &str,
+) -> Result<String, Error<'static>> {
+    let output = Command::new(&opt.python)
+        .arg(&opt.checker)
+        .arg(range)
+        .current_dir(repo.path())
+        .output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+
+    // the checker exits with an error when a commit fails, which is only a problem if it did not
+    // get to check anything
+    if !output.status.success() && !stdout.lines().any(|l| parse_checking_line(l).is_ok()) {
+        return Err(Error::Checker(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    Ok(stdout)
+}
+
+/// Check the commits of `range` with GCC's ChangeLog checker, or with gerris' own checks if
+/// `repo` does not contain the checker
+pub fn check_range_or_natively(
+    repo: &git::Repo,
+    opt: &ChangeLogsOpt,
+    range: &str,
+) -> Result<Vec<CheckLine>, Error<'static>> {
+    let checker = repo.path().join(&opt.checker);
+    if checker.exists() {
+        return Ok(parse_checker_output(&run_checker(repo, opt, range)?));
+    }
+
+    info!(
+        "`{}` not found: using gerris' own checks",
+        checker.display()
+    );
+
+    Ok(check::range(repo, range)?)
+}
+
+/// Generate the ChangeLog skeleton of a commit, using GCC's `mklog.py` if one is configured
+pub fn skeleton(
+    repo: &git::Repo,
+    opt: &ChangeLogsOpt,
+    hash: &str,
+) -> Result<String, Error<'static>> {
+    let patch = git::show(git::Commit(hash)).spawn(repo)?.stdout;
+    let Some(script) = &opt.mklog else {
+        let files = git::diff_tree(git::Commit(hash)).files(repo)?;
+        return Ok(mklog::skeleton(&files, &patch));
+    };
+
+    let mut mklog = Command::new(&opt.python)
+        .arg(script)
+        .current_dir(repo.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    mklog.stdin.take().unwrap().write_all(patch.as_bytes())?;
+
+    Ok(String::from_utf8(mklog.wait_with_output()?.stdout)?)
+}
+
+/// Commit which failed the checks, along with the ChangeLog skeleton which would fix it
+#[derive(Debug)]
+pub struct Report {
+    pub check_line: CheckLine,
+    pub skeleton: String,
+}
+
+/// Generate the skeleton of each commit which failed the checks
+fn reports<'clog>(
+    repo: &git::Repo,
+    opt: &ChangeLogsOpt,
+    check_lines: Vec<CheckLine>,
+) -> Result<Vec<Report>, Error<'clog>> {
+    check_lines
+        .into_iter()
+        .filter(|check_line| check_line.status == Status::Failed)
+        .map(|check_line| {
+            let skeleton = skeleton(repo, opt, &check_line.hash)?;
+
+            Ok(Report {
+                check_line,
+                skeleton,
+            })
+        })
+        .collect()
+}
+
+pub fn print_reports(reports: &[Report]) {
+    for report in reports {
+        println!("* Errors for commit {}:", report.check_line.hash);
+        for diagnostic in &report.check_line.diagnostics {
+            println!("  - {diagnostic}");
+        }
+        println!(
+            "* Changelog skeleton for commit {}:\n```{}```",
+            report.check_line.hash, report.skeleton
+        );
+    }
+}
+
+/// Handle the output of the checker given on `stdin`
+pub fn check_clog_checker_output<'clog>(opt: &ChangeLogsOpt) -> Result<Vec<Report>, Error<'clog>> {
+    let output = io::read_to_string(io::stdin())?;
+
+    reports(&git::Repo::new("."), opt, parse_checker_output(&output))
+}
+
+/// Run the checker on `range` in the GCC repository at `gcc_dir`, and handle its output
+pub fn check_clog_checker_range<'clog>(
+    opt: &ChangeLogsOpt,
+    gcc_dir: &Path,
+    range: &str,
+) -> Result<Vec<Report>, Error<'clog>> {
+    let repo = git::Repo::new(gcc_dir);
+    let output = run_checker(&repo, opt, range)?;
+
+    reports(&repo, opt, parse_checker_output(&output))
+}
+
+/// Check `range` in the current repository, with GCC's checker if it is there or with gerris'
+/// own checks otherwise
+pub fn check_clog_range<'clog>(
+    opt: &ChangeLogsOpt,
+    range: &str,
+) -> Result<Vec<Report>, Error<'clog>> {
+    let repo = git::Repo::new(".");
+    let check_lines = check_range_or_natively(&repo, opt, range)?;
+
+    reports(&repo, opt, check_lines)
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    #[test]
+    fn checker_output() {
+        let check_lines = parse_checker_output(_OUT);
+        let failed: Vec<&CheckLine> = check_lines
+            .iter()
+            .filter(|check_line| check_line.status == Status::Failed)
+            .collect();
+
+        assert_eq!(check_lines.len(), 77);
+        assert_eq!(failed.len(), 26);
+        assert_eq!(
+            failed[0],
+            &CheckLine {
+                hash: "71e2a04ec5668c6b1b7f5afecf6fdee4b54888fa".into(),
+                status: Status::Failed,
+                diagnostics: vec![Diagnostic::MissingLocation],
+            }
+        );
+        assert_eq!(
+            failed[1].diagnostics,
+            [Diagnostic::NewTopLevelFile(
+                ".github/workflows/commit-format.yml".into()
+            )]
+        );
+        assert_eq!(
+            failed[3].diagnostics[2],
+            Diagnostic::FileNotMentioned("gcc/rust/backend/rust-compile-stmt.cc".into())
+        );
+        assert!(check_lines[1].diagnostics.is_empty());
+    }
+
+    #[test]
+    fn multiline_diagnostics() {
+        let check_lines = parse_checker_output(_OUT);
+        let diagnostics = &check_lines
+            .iter()
+            .find(|check_line| check_line.hash.starts_with("d7c321da"))
+            .unwrap()
+            .diagnostics;
+
+        assert_eq!(diagnostics.len(), 14);
+        assert_eq!(
+            diagnostics[0],
+            Diagnostic::TrailingWhitespace("gcc/rust/ChangeLog:\n".into())
+        );
+        assert_eq!(diagnostics[1], Diagnostic::TrailingWhitespace("\n".into()));
+        assert_eq!(diagnostics[2], Diagnostic::MissingTab("\n".into()));
+        assert_eq!(
+            diagnostics[5],
+            Diagnostic::LineTooLong {
+                limit: 100,
+                line:
+                    "\t* expand/rust-attribute-visitor.cc (AttrVisitor::visit): Mention switching \
+                    on `macro.kind` once builtin macro invocations are properly handled\n"
+                        .into()
+            }
+        );
+        assert!(matches!(
+            diagnostics[13],
+            Diagnostic::MissingTab(ref line) if line.starts_with("This will be")
+        ));
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "trailing whitespace: \"gcc/rust/ChangeLog:\\n\""
+        );
+    }
+
+    #[test]
+    fn other_diagnostic() {
+        assert_eq!(
+            Diagnostic::parse("bad wrapping of parenthesis: \"\t* foo.cc (bar\""),
+            Diagnostic::Other("bad wrapping of parenthesis: \"\t* foo.cc (bar\"".into())
+        );
+    }
+
     static _OUT: &str = r#"
 Checking 71e2a04ec5668c6b1b7f5afecf6fdee4b54888fa: FAILED
 ERR: cannot find a ChangeLog location in message
@@ -0,0 +1,372 @@
+//! ChangeLog checks performed by gerris itself, following the rules of GCC's
+//! `contrib/gcc-changelog/git_check_commit.py` so that commits can be checked without a GCC tree
+
+use std::path::Path;
+
+use super::{CheckLine, Diagnostic, Status};
+use crate::git::{self, ChangedFile, GitCmd};
+
+/// Maximum length of a line of a ChangeLog, tabs counting as `TAB_WIDTH` characters
+pub(super) const LINE_LIMIT: usize = 100;
+pub(super) const TAB_WIDTH: usize = 8;
+
+/// Directories of GCC containing a ChangeLog, besides the top-level one. Only the ones commits
+/// from gccrs are likely to touch are listed.
+const CHANGELOG_DIRS: [&str; 16] = [
+    "config",
+    "contrib",
+    "gcc",
+    "gcc/ada",
+    "gcc/c",
+    "gcc/c-family",
+    "gcc/cp",
+    "gcc/fortran",
+    "gcc/go",
+    "gcc/jit",
+    "gcc/m2",
+    "gcc/rust",
+    "gcc/testsuite",
+    "include",
+    "libgcc",
+    "libgrust",
+];
+
+/// Trailers which can follow the ChangeLog entries without being indented
+const TRAILERS: [&str; 6] = [
+    "signed-off-by:",
+    "co-authored-by:",
+    "reviewed-by:",
+    "tested-by:",
+    "suggested-by:",
+    "reported-by:",
+];
+
+/// Directory of the ChangeLog a location header such as `gcc/rust/ChangeLog:` points to
+pub(super) fn location(line: &str) -> Option<&str> {
+    let line = line
+        .strip_prefix("for ")
+        .or_else(|| line.strip_prefix("For "))
+        .unwrap_or(line)
+        .trim_start_matches(' ');
+    let rest = line.trim_end().strip_suffix(':').unwrap_or(line.trim_end());
+    let dir = rest.strip_suffix("ChangeLog")?;
+
+    dir.chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "+-/_.".contains(c))
+        .then(|| dir.trim_end_matches('/'))
+}
+
+pub(super) fn line_length(line: &str) -> usize {
+    line.chars()
+        .map(|c| if c == '\t' { TAB_WIDTH } else { 1 })
+        .sum()
+}
+
+pub(super) fn is_trailer(line: &str) -> bool {
+    let line = line.to_ascii_lowercase();
+
+    TRAILERS.iter().any(|trailer| line.starts_with(trailer))
+}
+
+/// Files listed by an entry such as `\t* a.cc, b.cc (Foo::bar):`, and whether the list continues
+/// on the next line
+fn entry_files(content: &str) -> (Vec<&str>, bool) {
+    let (files, continued) = match content.split_once(':') {
+        Some((files, _)) => (files, false),
+        None => (content, content.trim_end().ends_with(',')),
+    };
+    let files = files.split_once('(').map_or(files, |(files, _)| files);
+
+    let files = files
+        .split(',')
+        .map(str::trim)
+        .filter(|file| !file.is_empty())
+        .collect();
+
+    (files, continued)
+}
+
+/// Deepest directory containing a ChangeLog in which `path` is, the top-level one being ""
+pub(super) fn changelog_dir<'a>(path: &str, locations: &[&'a str]) -> &'a str {
+    locations
+        .iter()
+        .chain(CHANGELOG_DIRS.iter())
+        .filter(|dir| Path::new(path).starts_with(dir))
+        .max_by_key(|dir| dir.len())
+        .copied()
+        .unwrap_or("")
+}
+
+/// Numbers of the PRs referenced as `PR 12345` or `PR component/12345` in `text`
+fn pr_numbers(text: &str) -> Vec<&str> {
+    text.match_indices("PR ")
+        .filter_map(|(start, _)| {
+            let reference = text[start + 3..].split_whitespace().next()?;
+            let number = reference.rsplit('/').next()?;
+            let number = number.trim_end_matches(|c: char| !c.is_ascii_digit());
+
+            (!number.is_empty() && number.chars().all(|c| c.is_ascii_digit())).then_some(number)
+        })
+        .collect()
+}
+
+/// Check a commit message against GCC's ChangeLog rules, given the files changed by the commit
+pub fn message(message: &str, files: &[ChangedFile]) -> Vec<Diagnostic> {
+    let lines: Vec<&str> = message.lines().collect();
+    let Some(start) = lines.iter().position(|line| location(line).is_some()) else {
+        return vec![Diagnostic::MissingLocation];
+    };
+
+    let locations: Vec<&str> = lines[start..]
+        .iter()
+        .filter_map(|line| location(line))
+        .collect();
+
+    let mut diagnostics = Vec::new();
+    let mut mentioned: Vec<String> = Vec::new();
+    let mut changelog_prs: Vec<&str> = Vec::new();
+    let mut dir = "";
+    let mut first_entry = false;
+    let mut continued = false;
+
+    for line in &lines[start..] {
+        if line.trim_end() != *line {
+            diagnostics.push(Diagnostic::TrailingWhitespace(line.to_string()));
+        }
+        if line_length(line) > LINE_LIMIT {
+            diagnostics.push(Diagnostic::LineTooLong {
+                limit: LINE_LIMIT,
+                line: line.to_string(),
+            });
+        }
+
+        if let Some(location) = location(line) {
+            dir = location;
+            first_entry = true;
+            continued = false;
+            continue;
+        }
+
+        if line.trim().is_empty() || is_trailer(line) {
+            continue;
+        }
+
+        let Some(content) = line.strip_prefix('\t') else {
+            diagnostics.push(Diagnostic::MissingTab(line.to_string()));
+            continue;
+        };
+
+        if let Some(reference) = content.strip_prefix("PR ") {
+            if !reference.contains('/') {
+                diagnostics.push(Diagnostic::MissingPrComponent(line.to_string()));
+            }
+            changelog_prs.extend(pr_numbers(content));
+            continue;
+        }
+
+        let files = match content.strip_prefix("* ") {
+            Some(entry) => {
+                first_entry = false;
+                let (files, next) = entry_files(entry);
+                continued = next;
+                files
+            }
+            None if first_entry => {
+                first_entry = false;
+                diagnostics.push(Diagnostic::MissingStar(line.to_string()));
+                continue;
+            }
+            None if continued => {
+                let (files, next) = entry_files(content);
+                continued = next;
+                files
+            }
+            None => continue,
+        };
+
+        mentioned.extend(files.into_iter().map(|file| match dir {
+            "" => file.to_string(),
+            dir => format!("{dir}/{file}"),
+        }));
+    }
+
+    let is_mentioned = |path: &str| {
+        mentioned
+            .iter()
+            .any(|file| file == path || (file.ends_with('/') && path.starts_with(file.as_str())))
+    };
+
+    for file in files {
+        if is_mentioned(&file.path) {
+            continue;
+        }
+
+        let top_level = changelog_dir(&file.path, &locations).is_empty();
+        diagnostics.push(match file.status {
+            git::Status::Added if top_level => Diagnostic::NewTopLevelFile(file.path.clone()),
+            _ => Diagnostic::FileNotMentioned(file.path.clone()),
+        });
+    }
+
+    for file in &mentioned {
+        if !file.ends_with('/') && !files.iter().any(|changed| &changed.path == file) {
+            diagnostics.push(Diagnostic::UnchangedFileMentioned(file.clone()));
+        }
+    }
+
+    let subject = lines.first().copied().unwrap_or_default();
+    for pr in pr_numbers(subject) {
+        if !changelog_prs.contains(&pr) {
+            diagnostics.push(Diagnostic::PrNotInChangeLog(pr.to_string()));
+        }
+    }
+
+    diagnostics
+}
+
+/// Check the message of a commit of `repo` against the files it changes
+pub fn commit(repo: &git::Repo, hash: &str) -> Result<CheckLine, git::Error> {
+    let message = git::log()
+        .amount(1)
+        .format(git::Format::Body)
+        .branch(git::Branch(hash))
+        .spawn(repo)?
+        .stdout;
+    let files = git::diff_tree(git::Commit(hash)).files(repo)?;
+
+    let diagnostics = self::message(&message, &files);
+
+    Ok(CheckLine {
+        hash: hash.to_string(),
+        status: if diagnostics.is_empty() {
+            Status::Success
+        } else {
+            Status::Failed
+        },
+        diagnostics,
+    })
+}
+
+/// Hashes of the commits of `range`, e.g. `gcc/trunk..HEAD`, from the oldest one, or of the
+/// single commit it names
+pub(super) fn commits(repo: &git::Repo, range: &str) -> Result<Vec<String>, git::Error> {
+    let hashes = if range.contains("..") {
+        git::rev_list_range(range).reverse().spawn(repo)?.stdout
+    } else {
+        git::rev_parse(range).spawn(repo)?.stdout
+    };
+
+    Ok(hashes.lines().map(String::from).collect())
+}
+
+/// Check every commit of `range`, e.g. `gcc/trunk..HEAD`, or the single commit it names
+pub fn range(repo: &git::Repo, range: &str) -> Result<Vec<CheckLine>, git::Error> {
+    commits(repo, range)?
+        .iter()
+        .map(|hash| commit(repo, hash))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid() {
+        let message = "gccrs: Fix pattern compilation\n\n\
+            Some description.\n\n\
+            gcc/rust/ChangeLog:\n\n\
+            \tPR rust/108111\n\
+            \t* backend/rust-compile-pattern.cc (CompilePatternCheckExpr::visit): Fix.\n\
+            \t* backend/rust-compile-pattern.h,\n\
+            \tbackend/rust-compile-stmt.cc: Likewise.\n\n\
+            gcc/testsuite/ChangeLog:\n\n\
+            \t* rust/compile/pattern.rs: New test.\n\n\
+            Signed-off-by: Arthur Cohen <arthur.cohen@embecosm.com>";
+        let files = [
+            ChangedFile::new(
+                git::Status::Modified,
+                "gcc/rust/backend/rust-compile-pattern.cc",
+            ),
+            ChangedFile::new(
+                git::Status::Modified,
+                "gcc/rust/backend/rust-compile-pattern.h",
+            ),
+            ChangedFile::new(
+                git::Status::Modified,
+                "gcc/rust/backend/rust-compile-stmt.cc",
+            ),
+            ChangedFile::new(git::Status::Added, "gcc/testsuite/rust/compile/pattern.rs"),
+        ];
+
+        assert_eq!(self::message(message, &files), []);
+    }
+
+    #[test]
+    fn missing_location() {
+        assert_eq!(
+            self::message("Add foo\n\nNo ChangeLog here.", &[]),
+            [Diagnostic::MissingLocation]
+        );
+    }
+
+    #[test]
+    fn files() {
+        let message = "Add workflow\n\ngcc/rust/ChangeLog:\n\n\t* bar.cc: Remove.\n";
+        let files = [
+            ChangedFile::new(git::Status::Added, ".github/workflows/commit-format.yml"),
+            ChangedFile::new(git::Status::Modified, "gcc/rust/parse/rust-parse-impl.h"),
+        ];
+
+        assert_eq!(
+            self::message(message, &files),
+            [
+                Diagnostic::NewTopLevelFile(".github/workflows/commit-format.yml".into()),
+                Diagnostic::FileNotMentioned("gcc/rust/parse/rust-parse-impl.h".into()),
+                Diagnostic::UnchangedFileMentioned("gcc/rust/bar.cc".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn formatting() {
+        let long = format!(
+            "\t* bar.cc: {}",
+            "Handle carriage returns properly. ".repeat(3)
+        );
+        let message = format!(
+            "PR 1234: Fix bar\n\ngcc/rust/ChangeLog: \n\n{long}\n\tPR 5678\n\n\
+            This will be necessary later."
+        );
+        let files = [ChangedFile::new(git::Status::Modified, "gcc/rust/bar.cc")];
+
+        assert_eq!(
+            self::message(&message, &files),
+            [
+                Diagnostic::TrailingWhitespace("gcc/rust/ChangeLog: ".into()),
+                Diagnostic::TrailingWhitespace(long.clone()),
+                Diagnostic::LineTooLong {
+                    limit: LINE_LIMIT,
+                    line: long.clone()
+                },
+                Diagnostic::MissingPrComponent("\tPR 5678".into()),
+                Diagnostic::MissingTab("This will be necessary later.".into()),
+                Diagnostic::PrNotInChangeLog("1234".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_star() {
+        let message = "Fix bar\n\ngcc/rust/ChangeLog:\n\n\tbar.cc: Fix.\n";
+        let files = [ChangedFile::new(git::Status::Modified, "gcc/rust/bar.cc")];
+
+        assert_eq!(
+            self::message(message, &files),
+            [
+                Diagnostic::MissingStar("\tbar.cc: Fix.".into()),
+                Diagnostic::FileNotMentioned("gcc/rust/bar.cc".into()),
+            ]
+        );
+    }
+}
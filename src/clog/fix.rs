@@ -0,0 +1,406 @@
+//! Automatic fixes of the mechanical errors reported by the ChangeLog checks, such as trailing
+//! whitespace, indentation with spaces, over-long lines or files missing from the ChangeLogs
+
+use std::io::{self, Write};
+
+use log::{info, warn};
+
+use super::check::{self, line_length, location, LINE_LIMIT, TAB_WIDTH};
+use super::{mklog, Diagnostic, Error};
+use crate::git::{self, ChangedFile, GitCmd, StartingPoint};
+
+/// Branch on which the commits are rewritten before moving the original branch to them
+const FIX_BRANCH: &str = "gerris-changelogs-fix";
+
+/// Message of a commit, along with its fixed version
+pub struct Fix {
+    pub hash: String,
+    pub message: String,
+    pub fixed: String,
+}
+
+impl Fix {
+    fn is_needed(&self) -> bool {
+        self.message != self.fixed
+    }
+}
+
+/// Split an over-long entry line at spaces, indenting the continuation lines with a tab
+fn wrap(line: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut rest = line.trim_start_matches('\t');
+
+    while line_length(rest) + TAB_WIDTH > LINE_LIMIT {
+        let spaces: Vec<usize> = rest
+            .match_indices(' ')
+            .map(|(i, _)| i)
+            .filter(|&i| !rest[..i].trim().is_empty())
+            .collect();
+        let Some(cut) = spaces
+            .iter()
+            .rev()
+            .find(|&&i| line_length(&rest[..i]) + TAB_WIDTH <= LINE_LIMIT)
+            .or(spaces.first())
+        else {
+            break;
+        };
+
+        lines.push(format!("\t{}", rest[..*cut].trim_end()));
+        rest = rest[*cut..].trim_start();
+    }
+
+    lines.push(format!("\t{rest}"));
+    lines
+}
+
+/// Strip trailing whitespace, indent the entries with tabs and wrap the lines which are too long
+fn format(message: &str) -> Vec<String> {
+    let lines: Vec<&str> = message.lines().map(str::trim_end).collect();
+    let start = lines
+        .iter()
+        .position(|line| location(line).is_some())
+        .unwrap_or(lines.len());
+
+    let mut formatted: Vec<String> = lines[..start].iter().map(|l| l.to_string()).collect();
+
+    for line in &lines[start..] {
+        let line = if location(line).is_some() || line.is_empty() || line.starts_with('\t') {
+            line.to_string()
+        } else if line.starts_with(' ') {
+            format!("\t{}", line.trim_start_matches(' '))
+        } else if line.starts_with("* ") {
+            format!("\t{line}")
+        } else {
+            line.to_string()
+        };
+
+        if line.starts_with('\t') && line_length(&line) > LINE_LIMIT {
+            formatted.extend(wrap(&line));
+        } else {
+            formatted.push(line);
+        }
+    }
+
+    formatted
+}
+
+/// Index after which to add a new ChangeLog to the message, before its trailers
+fn changelogs_end(lines: &[String]) -> usize {
+    lines
+        .iter()
+        .rposition(|line| !line.is_empty() && !check::is_trailer(line))
+        .map_or(lines.len(), |i| i + 1)
+}
+
+/// Insert the entries generated from `patch` for the files the message does not mention
+fn add_entries(mut lines: Vec<String>, files: &[ChangedFile], patch: &str) -> Vec<String> {
+    let diagnostics = check::message(&lines.join("\n"), files);
+    let entries = mklog::entries(files, patch);
+
+    if diagnostics.contains(&Diagnostic::MissingLocation) {
+        let at = changelogs_end(&lines);
+        let skeleton = mklog::skeleton(files, patch);
+        let skeleton = std::iter::once(String::new()).chain(skeleton.lines().map(String::from));
+
+        lines.splice(at..at, skeleton);
+        return lines;
+    }
+
+    let missing = entries.iter().filter(|entry| {
+        diagnostics.iter().any(|diagnostic| match diagnostic {
+            Diagnostic::FileNotMentioned(path) | Diagnostic::NewTopLevelFile(path) => {
+                path == &entry.path
+            }
+            _ => false,
+        })
+    });
+
+    for entry in missing {
+        let header = lines
+            .iter()
+            .position(|line| location(line) == Some(entry.dir));
+        let new_lines: Vec<String> = entry.text.lines().map(String::from).collect();
+
+        match header {
+            Some(header) => {
+                let end = lines[header + 1..]
+                    .iter()
+                    .position(|line| location(line).is_some())
+                    .map_or(lines.len(), |i| header + 1 + i);
+                let at = lines[header + 1..end]
+                    .iter()
+                    .rposition(|line| line.starts_with('\t'))
+                    .map_or(header + 1, |i| header + 2 + i);
+
+                lines.splice(at..at, new_lines);
+            }
+            None => {
+                let at = changelogs_end(&lines);
+                let block = [String::new(), mklog::header(entry.dir), String::new()]
+                    .into_iter()
+                    .chain(new_lines);
+
+                lines.splice(at..at, block);
+            }
+        }
+    }
+
+    lines
+}
+
+/// Fix the mechanical ChangeLog errors of a commit message, given the files changed by the commit
+/// and its patch as output by `git show`
+pub fn message(message: &str, files: &[ChangedFile], patch: &str) -> String {
+    add_entries(format(message), files, patch).join("\n")
+}
+
+/// Line by line difference between two messages, as shown by `diff` with the whole context
+fn diff(old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // length of the longest common subsequence of `old[i..]` and `new[j..]`
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut diff = String::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            diff.push_str(&format!(" {}\n", old[i]));
+            (i, j) = (i + 1, j + 1);
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            diff.push_str(&format!("-{}\n", old[i]));
+            i += 1;
+        } else {
+            diff.push_str(&format!("+{}\n", new[j]));
+            j += 1;
+        }
+    }
+
+    diff
+}
+
+/// Fixed message of every commit of `range`
+pub fn range(repo: &git::Repo, range: &str) -> Result<Vec<Fix>, Error<'static>> {
+    check::commits(repo, range)?
+        .into_iter()
+        .map(|hash| {
+            let message = git::log()
+                .amount(1)
+                .format(git::Format::Body)
+                .branch(git::Branch(&hash))
+                .spawn(repo)?
+                .stdout;
+            let files = git::diff_tree(git::Commit(&hash)).files(repo)?;
+            let patch = git::show(git::Commit(&hash)).spawn(repo)?.stdout;
+            let fixed = self::message(&message, &files, &patch);
+
+            Ok(Fix {
+                hash,
+                message,
+                fixed,
+            })
+        })
+        .collect()
+}
+
+fn confirm(count: usize, branch: &str) -> Result<bool, Error<'static>> {
+    print!("Rewrite {count} commits of `{branch}`? [y/N] ");
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Replay the commits from the first one which needs fixing on `FIX_BRANCH`, amending their
+/// messages
+fn replay(repo: &git::Repo, fixes: &[Fix]) -> Result<(), Error<'static>> {
+    git::branch()
+        .name(FIX_BRANCH)
+        .starting_point(StartingPoint::Commit(format!("{}^", fixes[0].hash)))
+        .spawn(repo)?;
+    git::switch(FIX_BRANCH).spawn(repo)?;
+
+    for fix in fixes {
+        git::cherry_pick(git::Commit(&fix.hash)).spawn(repo)?;
+
+        if fix.is_needed() {
+            git::commit().amend().message(&fix.fixed).spawn(repo)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Rewrite the messages of the commits of `range`, which must end at the current commit of the
+/// current branch, after showing the changes and asking for confirmation unless `yes` is set
+pub fn fix_range(range: &str, yes: bool) -> Result<(), Error<'static>> {
+    let repo = git::Repo::new(".");
+
+    let branch = git::rev_parse("HEAD").abbrev_ref().spawn(&repo)?.stdout;
+    if branch == "HEAD" {
+        return Err(Error::DetachedHead);
+    }
+
+    if !git::status().clean(&repo)? {
+        return Err(Error::DirtyWorkTree);
+    }
+
+    let end = range.rsplit_once("..").map_or(range, |(_, end)| end);
+    let end = end.trim_start_matches('.');
+    let end = if end.is_empty() { "HEAD" } else { end };
+    if git::rev_parse(end).spawn(&repo)?.stdout != git::rev_parse("HEAD").spawn(&repo)?.stdout {
+        return Err(Error::NotHead(range.to_string()));
+    }
+
+    // the branch is force-deleted when replaying fails, so never reuse one we did not create
+    if git::rev_parse(format!("refs/heads/{FIX_BRANCH}"))
+        .verify()
+        .spawn(&repo)
+        .is_ok()
+    {
+        return Err(Error::BranchExists(FIX_BRANCH));
+    }
+
+    // a single commit, as `check::commits` understands it, rather than all its ancestors
+    let listed = if range.contains("..") {
+        range.to_string()
+    } else {
+        format!("{range}^!")
+    };
+    if !git::rev_list_range(listed)
+        .merges()
+        .spawn(&repo)?
+        .stdout
+        .is_empty()
+    {
+        return Err(Error::Merges(range.to_string()));
+    }
+
+    let fixes = self::range(&repo, range)?;
+    let Some(first) = fixes.iter().position(Fix::is_needed) else {
+        info!("no ChangeLog errors which gerris can fix in `{range}`");
+        return Ok(());
+    };
+
+    let parent = format!("{}^", fixes[first].hash);
+    if git::rev_parse(&parent).verify().spawn(&repo).is_err() {
+        return Err(Error::RootCommit(fixes[first].hash.clone()));
+    }
+
+    for fix in fixes.iter().filter(|fix| fix.is_needed()) {
+        println!("--- {}\n+++ {}", fix.hash, fix.hash);
+        print!("{}", diff(&fix.message, &fix.fixed));
+    }
+
+    let count = fixes.iter().filter(|fix| fix.is_needed()).count();
+    if !yes && !confirm(count, &branch)? {
+        info!("leaving `{branch}` untouched");
+        return Ok(());
+    }
+
+    if let Err(e) = replay(&repo, &fixes[first..]) {
+        warn!("could not rewrite the commits, restoring `{branch}`");
+
+        // best effort, as the failure may have happened at any step
+        let _ = git::cherry_pick_abort().spawn(&repo);
+        let _ = git::switch(&branch).spawn(&repo);
+        let _ = git::branch().force_delete().name(FIX_BRANCH).spawn(&repo);
+
+        return Err(e);
+    }
+
+    git::branch()
+        .force()
+        .name(&branch)
+        .starting_point(StartingPoint::Branch(FIX_BRANCH))
+        .spawn(&repo)?;
+    git::switch(&branch).spawn(&repo)?;
+    git::branch().force_delete().name(FIX_BRANCH).spawn(&repo)?;
+
+    info!("rewrote {count} commits of `{branch}`");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formatting() {
+        let long = format!(
+            "        * bar.cc (Bar::baz): {}",
+            "Handle carriage returns properly. ".repeat(3)
+        );
+        let message = format!("Fix bar \n\ngcc/rust/ChangeLog: \n\n{long}\n* baz.cc: Likewise.");
+        let files = [
+            ChangedFile::new(git::Status::Modified, "gcc/rust/bar.cc"),
+            ChangedFile::new(git::Status::Modified, "gcc/rust/baz.cc"),
+        ];
+
+        assert_eq!(
+            self::message(&message, &files, ""),
+            "Fix bar\n\ngcc/rust/ChangeLog:\n\n\
+            \t* bar.cc (Bar::baz): Handle carriage returns properly. Handle carriage returns \
+            properly.\n\
+            \tHandle carriage returns properly.\n\
+            \t* baz.cc: Likewise."
+        );
+    }
+
+    #[test]
+    fn entries() {
+        let patch = "\
+diff --git a/gcc/rust/bar.cc b/gcc/rust/bar.cc
+--- a/gcc/rust/bar.cc
++++ b/gcc/rust/bar.cc
+@@ -1,6 +1,7 @@ Bar::baz (int qux)
+diff --git a/gcc/rust/baz.cc b/gcc/rust/baz.cc
+--- a/gcc/rust/baz.cc
++++ b/gcc/rust/baz.cc
+diff --git a/gcc/testsuite/rust/compile/bar.rs b/gcc/testsuite/rust/compile/bar.rs
+new file mode 100644
+";
+        let files = [
+            ChangedFile::new(git::Status::Modified, "gcc/rust/bar.cc"),
+            ChangedFile::new(git::Status::Modified, "gcc/rust/baz.cc"),
+            ChangedFile::new(git::Status::Added, "gcc/testsuite/rust/compile/bar.rs"),
+        ];
+        let message = "Fix bar\n\ngcc/rust/ChangeLog:\n\n\t* baz.cc: Fix.\n\n\
+            Signed-off-by: Arthur Cohen <arthur.cohen@embecosm.com>";
+
+        assert_eq!(
+            self::message(message, &files, patch),
+            "Fix bar\n\ngcc/rust/ChangeLog:\n\n\t* baz.cc: Fix.\n\t* bar.cc (Bar::baz):\n\n\
+            gcc/testsuite/ChangeLog:\n\n\t* rust/compile/bar.rs: New file.\n\n\
+            Signed-off-by: Arthur Cohen <arthur.cohen@embecosm.com>"
+        );
+        assert_eq!(
+            self::message("Fix bar", &files, patch),
+            format!("Fix bar\n\n{}", mklog::skeleton(&files, patch).trim_end())
+        );
+    }
+
+    #[test]
+    fn preview() {
+        assert_eq!(
+            diff(
+                "Fix bar\n\n\t* bar.cc: Fix. \n",
+                "Fix bar\n\n\t* bar.cc: Fix.\n\t* baz.cc:"
+            ),
+            " Fix bar\n \n-\t* bar.cc: Fix. \n+\t* bar.cc: Fix.\n+\t* baz.cc:\n"
+        );
+    }
+}
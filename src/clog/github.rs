@@ -0,0 +1,111 @@
+//! Report of the ChangeLog checks as a comment on a GitHub pull-request. gerris keeps a single
+//! comment up to date across runs, and deletes it once every commit passes the checks.
+
+use log::info;
+use octocrab::{models::issues::Comment, OctocrabBuilder};
+
+use super::{Error, Report};
+use crate::git;
+use crate::upstream::GithubRepo;
+
+/// Hidden marker identifying the comment posted by gerris
+const MARKER: &str = "<!-- gerris-changelogs -->";
+
+/// Markdown comment listing the errors and skeleton of each failing commit
+fn comment(reports: &[Report]) -> String {
+    let mut comment = format!(
+        "{MARKER}\n❌ {} commits fail GCC's ChangeLog checks:\n",
+        reports.len()
+    );
+
+    for report in reports {
+        let diagnostics: Vec<String> = report
+            .check_line
+            .diagnostics
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        comment.push_str(&format!(
+            "\n#### `{}`\n\n```\n{}\n```\n\n\
+            <details><summary>ChangeLog skeleton</summary>\n\n```\n{}\n```\n\n</details>\n",
+            git::short(&report.check_line.hash),
+            diagnostics.join("\n"),
+            report.skeleton.trim_end()
+        ));
+    }
+
+    comment
+}
+
+fn is_own(comment: &Comment) -> bool {
+    comment
+        .body
+        .as_deref()
+        .is_some_and(|body| body.starts_with(MARKER))
+}
+
+/// Post the reports on pull-request `number`, editing the comment of a previous run if there is
+/// one, or delete that comment if there is nothing left to report
+pub async fn post(
+    token: String,
+    repo: &GithubRepo,
+    number: u64,
+    reports: &[Report],
+) -> Result<(), Error<'static>> {
+    let instance = OctocrabBuilder::new().personal_token(token).build()?;
+    let issues = instance.issues(&repo.owner, &repo.name);
+
+    let page = issues.list_comments(number).per_page(100).send().await?;
+    let previous = instance.all_pages(page).await?.into_iter().find(is_own);
+
+    match (previous, reports) {
+        (Some(previous), []) => {
+            info!("all commits pass, deleting comment on pull-request #{number}...");
+            issues.delete_comment(previous.id).await?;
+        }
+        (None, []) => info!("all commits pass, nothing to comment on pull-request #{number}"),
+        (Some(previous), reports) => {
+            info!("updating comment on pull-request #{number}...");
+            issues.update_comment(previous.id, comment(reports)).await?;
+        }
+        (None, reports) => {
+            info!("commenting on pull-request #{number}...");
+            issues.create_comment(number, comment(reports)).await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clog::{CheckLine, Diagnostic, Status};
+
+    #[test]
+    fn markdown() {
+        let reports = [Report {
+            check_line: CheckLine {
+                hash: "d0dad09f5de7ddcb52b825b5d3cd4f1aee16f982".into(),
+                status: Status::Failed,
+                diagnostics: vec![
+                    Diagnostic::FileNotMentioned("gcc/rust/backend/rust-compile-pattern.cc".into()),
+                    Diagnostic::TrailingWhitespace("gcc/rust/ChangeLog:\n".into()),
+                ],
+            },
+            skeleton: "gcc/rust/ChangeLog:\n\n\t* backend/rust-compile-pattern.cc:\n".into(),
+        }];
+
+        let comment = comment(&reports);
+
+        assert!(comment.starts_with(MARKER));
+        assert!(comment.contains(
+            "#### `d0dad09f5de7`\n\n```\n\
+            changed file not mentioned in a ChangeLog: \"gcc/rust/backend/rust-compile-pattern.cc\"\n\
+            trailing whitespace: \"gcc/rust/ChangeLog:\\n\"\n```"
+        ));
+        assert!(comment
+            .contains("```\ngcc/rust/ChangeLog:\n\n\t* backend/rust-compile-pattern.cc:\n```"));
+    }
+}
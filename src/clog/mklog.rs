@@ -0,0 +1,351 @@
+//! Generation of ChangeLog skeletons from the patch of a commit, following what GCC's
+//! `contrib/mklog.py` does so that neither Python nor a GCC checkout are needed
+
+use std::collections::BTreeMap;
+
+use super::check::changelog_dir;
+use crate::git::{ChangedFile, Status};
+
+/// Extensions of the files for which the functions changed are listed
+const FUNCTION_EXTENSIONS: [&str; 8] = [".c", ".cc", ".cpp", ".C", ".h", ".inc", ".def", ".md"];
+
+/// Hunk headers of the changes of each file of a patch as output by `git show`, in the order in
+/// which the patch lists the files
+fn sections(patch: &str) -> Vec<Vec<String>> {
+    let mut files: Vec<Vec<String>> = Vec::new();
+
+    for line in patch.lines() {
+        if line.starts_with("diff --git ") {
+            files.push(Vec::new());
+        } else if let (Some(sections), Some(hunk)) = (files.last_mut(), line.strip_prefix("@@ ")) {
+            if let Some((_, section)) = hunk.split_once(" @@") {
+                sections.push(section.trim().to_string());
+            }
+        }
+    }
+
+    files
+}
+
+/// Remove the template arguments of a name, e.g. `Parser<ManagedTokenSource>::parse_type`
+fn strip_templates(name: &str) -> String {
+    let mut stripped = String::new();
+    let mut depth = 0usize;
+
+    for c in name.chars() {
+        match c {
+            '<' => depth += 1,
+            '>' if depth > 0 => depth -= 1,
+            c if depth == 0 => stripped.push(c),
+            _ => {}
+        }
+    }
+
+    stripped
+}
+
+fn is_identifier(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Name of the function, type or macro whose definition starts on `line`, as `mklog.py` would
+/// extract it from a hunk header
+fn function_name(line: &str) -> Option<String> {
+    if line.starts_with("/*") {
+        return None;
+    }
+
+    let mut words = line.split_whitespace();
+    if let Some(kind @ ("class" | "struct" | "union" | "enum")) = words.next() {
+        let name = words
+            .find(|word| !word.starts_with("GTY"))?
+            .split(|c: char| !is_identifier(c))
+            .next()
+            .filter(|name| !name.is_empty())?;
+
+        return Some(format!("{kind} {name}"));
+    }
+
+    if let Some(directive) = line.strip_prefix('#') {
+        let directive = directive.trim_start();
+        let name = directive
+            .strip_prefix("define")
+            .or_else(|| directive.strip_prefix("undef"))?
+            .trim_start()
+            .split(|c: char| !is_identifier(c))
+            .next()?;
+
+        return (!name.is_empty()).then(|| name.to_string());
+    }
+
+    // the function is the word preceding the first parenthesis which is not a function pointer
+    let open = line
+        .match_indices('(')
+        .find(|(i, _)| !line[i + 1..].trim_start().starts_with('*'))?
+        .0;
+    let before = line[..open].trim_end();
+    let start = before
+        .rfind(|c: char| c.is_whitespace() || c == '(' || c == ')')
+        .map_or(0, |i| i + 1);
+    let name = before[start..].trim_start_matches('*');
+
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        .then(|| strip_templates(name))
+}
+
+fn lists_functions(dir: &str, relative: &str) -> bool {
+    !dir.ends_with("testsuite")
+        && FUNCTION_EXTENSIONS
+            .iter()
+            .any(|ext| relative.ends_with(ext))
+}
+
+/// Path of `path` relative to the ChangeLog directory `dir`
+fn relative<'a>(path: &'a str, dir: &str) -> &'a str {
+    match dir {
+        "" => path,
+        dir => path
+            .strip_prefix(dir)
+            .map_or(path, |path| path.trim_start_matches('/')),
+    }
+}
+
+/// ChangeLog entry of a file, along with the functions changed by the hunks of `sections`
+fn entry(file: &ChangedFile, sections: &[String], dir: &str) -> String {
+    let path = relative(&file.path, dir);
+
+    match &file.status {
+        Status::Added => return format!("\t* {path}: New file.\n"),
+        Status::Deleted => return format!("\t* {path}: Removed.\n"),
+        Status::Renamed { from } => {
+            return format!("\t* {path}: Moved from {}.\n", relative(from, dir))
+        }
+        Status::Copied { from } => {
+            return format!("\t* {path}: Copied from {}.\n", relative(from, dir))
+        }
+        _ => {}
+    }
+
+    let mut functions: Vec<String> = Vec::new();
+    if lists_functions(dir, path) {
+        for name in sections.iter().filter_map(|s| function_name(s)) {
+            if !functions.contains(&name) {
+                functions.push(name);
+            }
+        }
+    }
+
+    match functions.split_first() {
+        None => format!("\t* {path}:\n"),
+        Some((first, rest)) => rest
+            .iter()
+            .fold(format!("\t* {path} ({first}):\n"), |entry, function| {
+                format!("{entry}\t({function}):\n")
+            }),
+    }
+}
+
+/// Entry generated for a file changed by a patch
+pub(super) struct Entry {
+    /// Directory of the ChangeLog the file belongs to, the top-level one being ""
+    pub dir: &'static str,
+    pub path: String,
+    pub text: String,
+}
+
+/// Generate the ChangeLog entry of each of the `files` changed by a commit, whose `patch` as
+/// output by `git show` gives the functions changed
+pub(super) fn entries(files: &[ChangedFile], patch: &str) -> Vec<Entry> {
+    let mut sections = sections(patch);
+    // the patch lists the files in the same order as `git diff-tree`, unless git was configured to
+    // detect renames differently, in which case the functions changed are left out
+    if sections.len() != files.len() {
+        sections = vec![Vec::new(); files.len()];
+    }
+
+    files
+        .iter()
+        .zip(sections)
+        .map(|(file, sections)| {
+            let dir = changelog_dir(&file.path, &[]);
+
+            Entry {
+                dir,
+                path: file.path.clone(),
+                text: entry(file, &sections, dir),
+            }
+        })
+        .collect()
+}
+
+/// Location line introducing the entries of the ChangeLog in `dir`
+pub(super) fn header(dir: &str) -> String {
+    match dir {
+        "" => String::from("ChangeLog:"),
+        dir => format!("{dir}/ChangeLog:"),
+    }
+}
+
+/// Generate the ChangeLog skeleton of a commit from the `files` it changes and its `patch` as
+/// output by `git show`, grouping the files by the ChangeLog they belong to
+pub fn skeleton(files: &[ChangedFile], patch: &str) -> String {
+    let mut changelogs: BTreeMap<&str, String> = BTreeMap::new();
+
+    for entry in entries(files, patch) {
+        changelogs
+            .entry(entry.dir)
+            .or_default()
+            .push_str(&entry.text);
+    }
+
+    changelogs
+        .into_iter()
+        .map(|(dir, entries)| format!("{}\n\n{entries}", header(dir)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn function_names() {
+        assert_eq!(
+            function_name("Parser<ManagedTokenSource>::parse_type (bool save_errors)").as_deref(),
+            Some("Parser::parse_type")
+        );
+        assert_eq!(
+            function_name("CompilePatternCheckExpr::visit (HIR::RangePattern &pattern)").as_deref(),
+            Some("CompilePatternCheckExpr::visit")
+        );
+        assert_eq!(
+            function_name("class GTY(()) TokenCollector : public AST::ASTVisitor").as_deref(),
+            Some("class TokenCollector")
+        );
+        assert_eq!(
+            function_name("#define RUST_ATTR_COUNT 12").as_deref(),
+            Some("RUST_ATTR_COUNT")
+        );
+        assert_eq!(function_name("/* Compile a pattern.  */"), None);
+        assert_eq!(function_name("namespace Rust {"), None);
+    }
+
+    #[test]
+    fn paths() {
+        let patch = "\
+diff --git \"a/gcc/rust/caf\\303\\251.cc\" \"b/gcc/rust/caf\\303\\251.cc\"
+index 257cc56..5716ca5 100644
+--- \"a/gcc/rust/caf\\303\\251.cc\"
++++ \"b/gcc/rust/caf\\303\\251.cc\"
+@@ -1,3 +1,4 @@ Cafe::brew ()
++++ b/not/a/header.cc
+diff --git a/gcc/rust/\u{e9} b/gcc/rust/e
+similarity index 90%
+rename from gcc/rust/\u{e9}
+rename to gcc/rust/e
+diff --git a/gcc/rust/a.h b/gcc/rust/b.h
+similarity index 100%
+copy from gcc/rust/a.h
+copy to gcc/rust/b.h
+diff --git \"a/gcc/rust/x \\\"y\\\".cc\" \"b/gcc/rust/x \\\"y\\\".cc\"
+deleted file mode 100644
+index 257cc56..0000000
+";
+        // the paths come from `git diff-tree -z`, which does not quote them
+        let files = [
+            ChangedFile::new(Status::Modified, "gcc/rust/caf\u{e9}.cc"),
+            ChangedFile::new(
+                Status::Renamed {
+                    from: "gcc/rust/\u{e9}".into(),
+                },
+                "gcc/rust/e",
+            ),
+            ChangedFile::new(
+                Status::Copied {
+                    from: "gcc/rust/a.h".into(),
+                },
+                "gcc/rust/b.h",
+            ),
+            ChangedFile::new(Status::Deleted, "gcc/rust/x \"y\".cc"),
+        ];
+
+        assert_eq!(
+            skeleton(&files, patch),
+            "gcc/rust/ChangeLog:\n\n\
+            \t* caf\u{e9}.cc (Cafe::brew):\n\
+            \t* e: Moved from \u{e9}.\n\
+            \t* b.h: Copied from a.h.\n\
+            \t* x \"y\".cc: Removed.\n"
+        );
+
+        // the functions are left out when the patch does not list the same files
+        assert_eq!(
+            skeleton(&files[..1], patch),
+            "gcc/rust/ChangeLog:\n\n\t* caf\u{e9}.cc:\n"
+        );
+    }
+
+    #[test]
+    fn generate() {
+        let patch = "\
+commit 2f2cf24e3c1ab2f7b1c0a1d1c1f0e0d0c0b0a090
+Author: Arthur Cohen <arthur.cohen@embecosm.com>
+
+    gccrs: Fix pattern compilation
+
+diff --git a/gcc/rust/backend/rust-compile-pattern.cc b/gcc/rust/backend/rust-compile-pattern.cc
+index 257cc56..5716ca5 100644
+--- a/gcc/rust/backend/rust-compile-pattern.cc
++++ b/gcc/rust/backend/rust-compile-pattern.cc
+@@ -23,6 +23,7 @@ CompilePatternCheckExpr::visit (HIR::PathInExpression &pattern)
++  // fix
+@@ -80,6 +81,7 @@ CompilePatternCheckExpr::visit (HIR::RangePattern &pattern)
++  // fix
+@@ -90,6 +92,7 @@ CompilePatternCheckExpr::visit (HIR::RangePattern &pattern)
++  // fix
+diff --git a/gcc/rust/parse/rust-parse-impl.h b/gcc/rust/parse/rust-parse-impl.h
+index 257cc56..5716ca5 100644
+--- a/gcc/rust/parse/rust-parse-impl.h
++++ b/gcc/rust/parse/rust-parse-impl.h
+@@ -12,6 +12,7 @@ Parser<ManagedTokenSource>::parse_type (bool save_errors)
++  // fix
+diff --git a/gcc/rust/old.cc b/gcc/rust/old.cc
+deleted file mode 100644
+index 257cc56..0000000
+diff --git a/gcc/testsuite/rust/compile/pattern.rs b/gcc/testsuite/rust/compile/pattern.rs
+new file mode 100644
+index 0000000..257cc56
+diff --git a/libgrust/a.cc b/libgrust/b.cc
+similarity index 100%
+rename from libgrust/a.cc
+rename to libgrust/b.cc
+";
+        let files = [
+            ChangedFile::new(Status::Modified, "gcc/rust/backend/rust-compile-pattern.cc"),
+            ChangedFile::new(Status::Modified, "gcc/rust/parse/rust-parse-impl.h"),
+            ChangedFile::new(Status::Deleted, "gcc/rust/old.cc"),
+            ChangedFile::new(Status::Added, "gcc/testsuite/rust/compile/pattern.rs"),
+            ChangedFile::new(
+                Status::Renamed {
+                    from: "libgrust/a.cc".into(),
+                },
+                "libgrust/b.cc",
+            ),
+        ];
+
+        assert_eq!(
+            skeleton(&files, patch),
+            "gcc/rust/ChangeLog:\n\n\
+            \t* backend/rust-compile-pattern.cc (CompilePatternCheckExpr::visit):\n\
+            \t* parse/rust-parse-impl.h (Parser::parse_type):\n\
+            \t* old.cc: Removed.\n\
+            \n\
+            gcc/testsuite/ChangeLog:\n\n\
+            \t* rust/compile/pattern.rs: New file.\n\
+            \n\
+            libgrust/ChangeLog:\n\n\
+            \t* b.cc: Moved from a.cc.\n"
+        );
+    }
+}
@@ -0,0 +1,462 @@
+//! Configuration file holding the defaults of every subcommand, so that cron jobs and CI can share
+//! a single checked-in file instead of long command lines. Every value is optional: flags given
+//! on the command line take precedence over the file, which takes precedence over gerris' own
+//! defaults.
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::clog;
+use crate::upstream::{self, GithubRepo, OnConflict};
+
+/// Name of the configuration file looked up in the work directory
+pub const FILE_NAME: &str = "gerris.toml";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("could not read `{}`: {source}", .path.display())]
+    Io { path: PathBuf, source: io::Error },
+    #[error("invalid configuration in `{}`: {source}", .path.display())]
+    Toml {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+}
+
+/// Use the values given on the command line, falling back on the ones from the configuration file
+pub fn or_config<T>(cli: Vec<T>, config: Vec<T>) -> Vec<T> {
+    if cli.is_empty() {
+        config
+    } else {
+        cli
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    pub upstream: Upstream,
+    pub changelogs: ChangeLogs,
+}
+
+/// Defaults for the `upstream` subcommand
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Upstream {
+    pub to: Option<String>,
+    pub dirs: Vec<String>,
+    pub on_conflict: Option<OnConflict>,
+    pub strategy: Option<String>,
+    pub strategy_options: Vec<String>,
+    pub remotes: Remotes,
+    pub pull_request: PullRequest,
+    pub mail: Mail,
+    pub strict: bool,
+}
+
+/// GCC scripts used to check ChangeLogs and generate their skeletons, as given on the command
+/// line or in the configuration file
+#[derive(Args, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ChangeLogs {
+    #[arg(
+        long,
+        help = "python interpreter with which to run GCC's scripts [default: python3]"
+    )]
+    pub python: Option<String>,
+
+    #[arg(
+        long,
+        help = "path to GCC's `mklog.py` script, to generate ChangeLog skeletons with it instead of natively"
+    )]
+    pub mklog: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "path to GCC's ChangeLog checker [default: contrib/gcc-changelog/git_check_commit.py]"
+    )]
+    pub checker: Option<PathBuf>,
+}
+
+/// Remotes, branches and repositories involved in the upstream workflow, as given on the command
+/// line or in the configuration file
+#[derive(Args, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Remotes {
+    #[arg(
+        long,
+        help = "remote pointing to gccrs' repository [default: upstream]"
+    )]
+    pub upstream_remote: Option<String>,
+
+    #[arg(
+        long,
+        help = "branch of gccrs' repository containing the commits to upstream [default: master]"
+    )]
+    pub upstream_branch: Option<String>,
+
+    #[arg(long, help = "remote pointing to GCC's repository [default: gcc]")]
+    pub gcc_remote: Option<String>,
+
+    #[arg(
+        long,
+        help = "branch of GCC's repository on which commits are upstreamed [default: trunk]"
+    )]
+    pub gcc_branch: Option<String>,
+
+    #[arg(
+        long,
+        help = "remote to which the prepared branch is pushed [default: origin]"
+    )]
+    pub push_remote: Option<String>,
+
+    #[arg(
+        long,
+        help = "GitHub repository on which to open the pull-request, as `owner/name` [default: rust-gcc/gccrs]"
+    )]
+    pub repo: Option<GithubRepo>,
+
+    #[arg(
+        long,
+        help = "owner of the GitHub fork corresponding to the push remote [default: cohenarthur]"
+    )]
+    pub fork_owner: Option<String>,
+}
+
+impl Remotes {
+    /// Use the values of `self`, falling back on the ones of `other` when they are missing
+    pub fn or(self, other: Remotes) -> Remotes {
+        Remotes {
+            upstream_remote: self.upstream_remote.or(other.upstream_remote),
+            upstream_branch: self.upstream_branch.or(other.upstream_branch),
+            gcc_remote: self.gcc_remote.or(other.gcc_remote),
+            gcc_branch: self.gcc_branch.or(other.gcc_branch),
+            push_remote: self.push_remote.or(other.push_remote),
+            repo: self.repo.or(other.repo),
+            fork_owner: self.fork_owner.or(other.fork_owner),
+        }
+    }
+
+    /// Fill in the missing values with gerris' defaults
+    pub fn resolve(self) -> upstream::Remotes {
+        let default = upstream::Remotes::default();
+
+        upstream::Remotes {
+            upstream_remote: self.upstream_remote.unwrap_or(default.upstream_remote),
+            upstream_branch: self.upstream_branch.unwrap_or(default.upstream_branch),
+            gcc_remote: self.gcc_remote.unwrap_or(default.gcc_remote),
+            gcc_branch: self.gcc_branch.unwrap_or(default.gcc_branch),
+            push_remote: self.push_remote.unwrap_or(default.push_remote),
+            repo: self.repo.unwrap_or(default.repo),
+            fork_owner: self.fork_owner.unwrap_or(default.fork_owner),
+        }
+    }
+}
+
+impl ChangeLogs {
+    /// Use the values of `self`, falling back on the ones of `other` when they are missing
+    pub fn or(self, other: ChangeLogs) -> ChangeLogs {
+        ChangeLogs {
+            python: self.python.or(other.python),
+            mklog: self.mklog.or(other.mklog),
+            checker: self.checker.or(other.checker),
+        }
+    }
+
+    /// Fill in the missing values with gerris' defaults
+    pub fn resolve(self) -> clog::ChangeLogsOpt {
+        let default = clog::ChangeLogsOpt::default();
+
+        clog::ChangeLogsOpt {
+            python: self.python.unwrap_or(default.python),
+            mklog: self.mklog,
+            checker: self.checker.unwrap_or(default.checker),
+        }
+    }
+}
+
+/// Reviewers, labels and other settings applied to the pull-request once it is created
+#[derive(Args, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PullRequest {
+    #[arg(long = "reviewer", help = "user from whom to request a review")]
+    pub reviewers: Vec<String>,
+
+    #[arg(
+        long = "team-reviewer",
+        help = "team from which to request a review, without the organization"
+    )]
+    pub team_reviewers: Vec<String>,
+
+    #[arg(long = "label", help = "label to add to the pull-request")]
+    pub labels: Vec<String>,
+
+    #[arg(long, help = "number of the milestone to set on the pull-request")]
+    pub milestone: Option<u64>,
+
+    #[arg(
+        long,
+        overrides_with = "no_draft",
+        help = "open the pull-request as a draft"
+    )]
+    pub draft: bool,
+
+    #[arg(
+        long,
+        overrides_with = "draft",
+        help = "open the pull-request as ready for review even if `draft` is set in the configuration file"
+    )]
+    #[serde(skip)]
+    pub no_draft: bool,
+}
+
+impl PullRequest {
+    /// Use the values of `self`, falling back on the ones of `other` when they are missing
+    pub fn or(self, other: PullRequest) -> PullRequest {
+        PullRequest {
+            reviewers: or_config(self.reviewers, other.reviewers),
+            team_reviewers: or_config(self.team_reviewers, other.team_reviewers),
+            labels: or_config(self.labels, other.labels),
+            milestone: self.milestone.or(other.milestone),
+            draft: !self.no_draft && (self.draft || other.draft),
+            no_draft: false,
+        }
+    }
+}
+
+impl Display for PullRequest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let reviewers = self
+            .reviewers
+            .iter()
+            .map(|user| format!("@{user}"))
+            .chain(
+                self.team_reviewers
+                    .iter()
+                    .map(|team| format!("team {team}")),
+            )
+            .collect::<Vec<_>>();
+
+        if !reviewers.is_empty() {
+            writeln!(f, "reviewers: {}", reviewers.join(", "))?;
+        }
+        if !self.labels.is_empty() {
+            writeln!(f, "labels: {}", self.labels.join(", "))?;
+        }
+        if let Some(milestone) = self.milestone {
+            writeln!(f, "milestone: {milestone}")?;
+        }
+        if self.draft {
+            writeln!(f, "draft")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Recipients of the patch series sent to GCC's mailing lists, and how to send it
+#[derive(Args, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Mail {
+    #[arg(
+        id = "mail_to",
+        long = "mail-to",
+        help = "recipient of the patch series [default: gcc-patches@gcc.gnu.org]"
+    )]
+    pub to: Vec<String>,
+
+    #[arg(
+        id = "mail_cc",
+        long = "mail-cc",
+        help = "recipient in copy of the patch series"
+    )]
+    pub cc: Vec<String>,
+
+    #[arg(
+        id = "mail_from",
+        long = "mail-from",
+        help = "sender of the patch series, as `Name <address>`"
+    )]
+    pub from: Option<String>,
+
+    #[arg(long, help = "SMTP server through which to send the patch series")]
+    pub smtp_server: Option<String>,
+
+    #[arg(
+        long,
+        help = "port of the SMTP server [default: depends on the encryption]"
+    )]
+    pub smtp_port: Option<u16>,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "encryption of the SMTP connection [default: starttls]"
+    )]
+    pub smtp_encryption: Option<upstream::Encryption>,
+
+    #[arg(long, help = "user to authenticate as on the SMTP server")]
+    pub smtp_user: Option<String>,
+
+    #[arg(
+        long,
+        help = "file containing the SMTP password, instead of `GERRIS_SMTP_PASSWORD`"
+    )]
+    pub smtp_password_file: Option<PathBuf>,
+
+    #[arg(
+        id = "mail_delay",
+        long = "mail-delay",
+        value_name = "SECONDS",
+        help = "time to wait between two messages [default: 1]"
+    )]
+    pub delay: Option<u64>,
+}
+
+impl Mail {
+    /// Use the values of `self`, falling back on the ones of `other` when they are missing
+    pub fn or(self, other: Mail) -> Mail {
+        Mail {
+            to: or_config(self.to, other.to),
+            cc: or_config(self.cc, other.cc),
+            from: self.from.or(other.from),
+            smtp_server: self.smtp_server.or(other.smtp_server),
+            smtp_port: self.smtp_port.or(other.smtp_port),
+            smtp_encryption: self.smtp_encryption.or(other.smtp_encryption),
+            smtp_user: self.smtp_user.or(other.smtp_user),
+            smtp_password_file: self.smtp_password_file.or(other.smtp_password_file),
+            delay: self.delay.or(other.delay),
+        }
+    }
+}
+
+impl Config {
+    fn parse(path: &Path, content: &str) -> Result<Config, Error> {
+        toml::from_str(content).map_err(|source| Error::Toml {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    /// Load the configuration file given on the command line, or the one found in `work` if
+    /// there is any. A missing file is only an error when it was given explicitly.
+    pub fn load(explicit: Option<&Path>, work: &Path) -> Result<Config, Error> {
+        let path = explicit
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| work.join(FILE_NAME));
+
+        match fs::read_to_string(&path) {
+            Ok(content) => Config::parse(&path, &content),
+            Err(e) if explicit.is_none() && e.kind() == io::ErrorKind::NotFound => {
+                Ok(Config::default())
+            }
+            Err(source) => Err(Error::Io { path, source }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let config = Config::parse(
+            Path::new(FILE_NAME),
+            r#"
+[upstream]
+to = "gcc-patch-dev"
+dirs = ["gcc/rust", "libgrust"]
+on-conflict = "skip"
+strategy-options = ["theirs"]
+
+[upstream.remotes]
+gcc-remote = "gcc-mirror"
+repo = "cohenarthur/gccrs"
+
+[upstream.pull-request]
+reviewers = ["cohenarthur", "p-e-p", "philberty"]
+labels = ["upstream"]
+draft = true
+
+[changelogs]
+python = "python3.11"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.upstream.to.as_deref(), Some("gcc-patch-dev"));
+        assert_eq!(config.upstream.dirs, ["gcc/rust", "libgrust"]);
+        assert_eq!(config.upstream.on_conflict, Some(OnConflict::Skip));
+        assert_eq!(config.upstream.strategy, None);
+        assert_eq!(config.upstream.pull_request.labels, ["upstream"]);
+        assert_eq!(config.changelogs.python.as_deref(), Some("python3.11"));
+        assert_eq!(config.changelogs.mklog, None);
+
+        let cli = Remotes {
+            gcc_remote: Some("gcc-local".into()),
+            ..Remotes::default()
+        };
+        let remotes = cli.or(config.upstream.remotes).resolve();
+
+        assert_eq!(remotes.gcc_remote, "gcc-local");
+        assert_eq!(remotes.repo.to_string(), "cohenarthur/gccrs");
+        assert_eq!(remotes.upstream_ref(), "upstream/master");
+
+        let cli = PullRequest {
+            reviewers: vec!["CohenArthur".into()],
+            ..PullRequest::default()
+        };
+        let pr = cli.or(config.upstream.pull_request);
+
+        assert_eq!(pr.reviewers, ["CohenArthur"]);
+        assert_eq!(pr.labels, ["upstream"]);
+        assert!(pr.draft);
+
+        let cli = PullRequest {
+            no_draft: true,
+            ..PullRequest::default()
+        };
+
+        assert!(!cli.or(pr).draft);
+    }
+
+    #[test]
+    fn example() {
+        let path = Path::new("gerris.example.toml");
+        let config = Config::parse(path, include_str!("../gerris.example.toml")).unwrap();
+
+        assert_eq!(config.upstream.mail.smtp_port, Some(587));
+        assert_eq!(
+            config.changelogs.checker.as_deref(),
+            Some(Path::new("contrib/gcc-changelog/git_check_commit.py"))
+        );
+    }
+
+    #[test]
+    fn unknown_field() {
+        let err = Config::parse(
+            Path::new(FILE_NAME),
+            "[upstream]\nbase = \"gcc-patch-dev\"\n",
+        );
+
+        assert!(matches!(err, Err(Error::Toml { .. })));
+    }
+
+    #[test]
+    fn load() {
+        let work = Path::new("/nonexistent/gerris");
+
+        assert_eq!(Config::load(None, work).unwrap(), Config::default());
+        assert!(matches!(
+            Config::load(Some(&work.join(FILE_NAME)), work),
+            Err(Error::Io { .. })
+        ));
+    }
+}
@@ -1,5 +1,9 @@
-use std::process::{self, Command, Stdio};
-use std::{fmt, io, str};
+use std::ffi::OsStr;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::Arc;
+use std::{io, str};
 
 use thiserror::Error;
 
@@ -7,32 +11,171 @@ use thiserror::Error;
 mod branch;
 mod cherry_pick;
 mod commit;
+mod diff_tree;
 mod fetch;
+mod format_patch;
 mod log;
 mod push;
 mod rev_list;
+mod rev_parse;
+mod runner;
+mod show;
+mod status;
 mod switch;
 
 pub use branch::{branch, StartingPoint};
-pub use cherry_pick::cherry_pick;
+pub use cherry_pick::{cherry_pick, cherry_pick_abort};
 pub use commit::commit;
+pub use diff_tree::{diff_tree, ChangedFile, Status};
 pub use fetch::fetch;
+pub use format_patch::{format_patch, format_patch_commits};
 pub use log::log;
 pub use push::push;
-pub use rev_list::rev_list;
+pub use rev_list::{rev_list, rev_list_range, Mark};
+pub use rev_parse::rev_parse;
+pub use runner::{Process, RunFuture, Runner, Scripted};
+pub use show::show;
+pub use status::status;
 pub use switch::switch;
 
 #[derive(Debug, Error)]
 pub enum Error {
+    #[error("could not run git: {0}")]
     IO(#[from] io::Error),
-    Status(process::Output),
+    #[error("git output is not valid UTF-8: {0}")]
     Utf8(#[from] str::Utf8Error),
+    #[error("{}", conflict_message(.commit, .files))]
+    CherryPickConflict {
+        commit: Option<String>,
+        files: Vec<String>,
+    },
+    #[error("unknown remote `{0}`")]
+    UnknownRemote(String),
+    #[error("unknown revision or reference `{0}`")]
+    MissingRef(String),
+    #[error("push of `{0}` rejected: the remote contains work that is not present locally")]
+    NonFastForward(String),
+    #[error("authentication failed: {0}")]
+    Auth(String),
+    #[error("nothing to commit")]
+    NothingToCommit,
+    #[error("{}", status_message(.code, .stderr))]
+    Status { code: Option<i32>, stderr: String },
 }
 
-// FIXME:
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{self:#?}")
+fn conflict_message(commit: &Option<String>, files: &[String]) -> String {
+    let commit = commit.as_deref().unwrap_or("commit");
+    if files.is_empty() {
+        format!("could not cherry-pick {commit}: conflict")
+    } else {
+        format!(
+            "could not cherry-pick {commit}: conflict in {}",
+            files.join(", ")
+        )
+    }
+}
+
+fn status_message(code: &Option<i32>, stderr: &str) -> String {
+    match code {
+        Some(code) => format!("git exited with status {code}: {}", stderr.trim_end()),
+        None => format!("git was killed by a signal: {}", stderr.trim_end()),
+    }
+}
+
+/// Fetch the first quoted string in `line`, e.g. `'origin'` in
+/// `fatal: 'origin' does not appear to be a git repository`
+fn quoted(line: &str) -> Option<&str> {
+    let start = line.find('\'')? + 1;
+    let len = line[start..].find('\'')?;
+
+    Some(&line[start..start + len])
+}
+
+impl Error {
+    /// Classify a failed git invocation based on its exit code and output. Failures which
+    /// cannot be recognized are kept as an [`Error::Status`].
+    fn classify(code: Option<i32>, stdout: &str, stderr: &str) -> Error {
+        let files: Vec<String> = stdout
+            .lines()
+            .filter(|line| line.starts_with("CONFLICT"))
+            .filter_map(|line| line.rsplit_once(" in ").map(|(_, file)| file.to_string()))
+            .collect();
+
+        if !files.is_empty() || stderr.contains("could not apply") {
+            let commit = stderr.lines().find_map(|line| {
+                line.strip_prefix("error: could not apply ")
+                    .and_then(|rest| rest.split("...").next())
+                    .map(str::to_string)
+            });
+
+            return Error::CherryPickConflict { commit, files };
+        }
+
+        for line in stderr.lines() {
+            if line.contains("does not appear to be a git repository")
+                || line.starts_with("error: No such remote")
+            {
+                if let Some(remote) = quoted(line) {
+                    return Error::UnknownRemote(remote.to_string());
+                }
+            }
+
+            if line.contains("unknown revision or path not in the working tree")
+                || line.starts_with("fatal: bad revision")
+                || line.starts_with("fatal: Not a valid object name")
+                || line.starts_with("fatal: invalid reference")
+                || line.starts_with("fatal: bad object")
+            {
+                let reference = quoted(line)
+                    .or_else(|| line.split_whitespace().last())
+                    .unwrap_or(line)
+                    .trim_end_matches('.');
+
+                return Error::MissingRef(reference.to_string());
+            }
+
+            if line.contains("[rejected]")
+                && (line.contains("non-fast-forward")
+                    || line.contains("fetch first")
+                    || line.contains("stale info"))
+            {
+                let reference = line
+                    .split("[rejected]")
+                    .nth(1)
+                    .and_then(|rest| rest.split('(').next())
+                    .unwrap_or_default()
+                    .trim();
+
+                return Error::NonFastForward(reference.to_string());
+            }
+
+            if line.contains("Authentication failed")
+                || line.contains("Permission denied")
+                || line.contains("could not read Username")
+                || line.contains("The requested URL returned error: 403")
+            {
+                return Error::Auth(line.trim_start_matches("fatal: ").to_string());
+            }
+        }
+
+        if stdout.contains("nothing to commit") || stderr.contains("nothing to commit") {
+            return Error::NothingToCommit;
+        }
+
+        Error::Status {
+            code,
+            stderr: stderr.to_string(),
+        }
+    }
+}
+
+impl From<process::Output> for Error {
+    fn from(output: process::Output) -> Error {
+        Error::classify(
+            output.status.code(),
+            &String::from_utf8_lossy(&output.stdout),
+            &String::from_utf8_lossy(&output.stderr),
+        )
     }
 }
 
@@ -42,6 +185,7 @@ pub enum Format {
     Hash,
     Title,
     Body,
+    Author,
 }
 
 impl Format {
@@ -50,6 +194,7 @@ impl Format {
             Format::Hash => "%h",
             Format::Title => "%s",
             Format::Body => "%B",
+            Format::Author => "%an",
         }
     }
 }
@@ -58,6 +203,11 @@ pub struct Branch<T: Into<String>>(pub T);
 pub struct Commit<T: Into<String>>(pub T);
 pub struct Remote<T: Into<String>>(pub T);
 
+/// Abbreviated hash of a commit, as displayed to users
+pub fn short(hash: &str) -> &str {
+    &hash[..hash.len().min(12)]
+}
+
 pub struct Output {
     pub status: process::ExitStatus,
     pub stdout: String,
@@ -79,22 +229,209 @@ impl TryFrom<process::Output> for Output {
     }
 }
 
+/// Repository or work-tree on which git commands are run. Its path is given to git using `-C`,
+/// so that commands never depend on the current directory of the process.
+#[derive(Clone)]
+pub struct Repo {
+    path: PathBuf,
+    runner: Arc<dyn Runner>,
+}
+
+impl Repo {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Repo {
+        Repo::with_runner(path, Process)
+    }
+
+    /// Create a repository whose commands are executed by `runner` instead of a real git process
+    pub fn with_runner<P: Into<PathBuf>, R: Runner + 'static>(path: P, runner: R) -> Repo {
+        Repo {
+            path: path.into(),
+            runner: Arc::new(runner),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+fn check(output: process::Output) -> Result<Output, Error> {
+    if output.status.success() {
+        Ok(output.try_into()?)
+    } else {
+        Err(output.into())
+    }
+}
+
 pub trait GitCmd: Sized {
-    // FIXME: Spawn needs to check the exit code and encode that in its return type - non-zero should be Err
-    fn spawn(self) -> Result<Output, Error> {
+    fn spawn(self, repo: &Repo) -> Result<Output, Error> {
+        check(repo.runner.run(self.command(repo))?)
+    }
+
+    /// Asynchronous version of [`GitCmd::spawn`], which does not block the runtime while git is
+    /// running
+    fn spawn_async(self, repo: &Repo) -> impl Future<Output = Result<Output, Error>> + Send {
+        let output = repo.runner.run_async(self.command(repo));
+
+        async move { check(output.await?) }
+    }
+
+    /// Build the command which [`GitCmd::spawn`] would run, without running it
+    fn command(self, repo: &Repo) -> Command {
         let mut cmd = Command::new("git");
-        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+        cmd.arg("-C").arg(repo.path());
 
         self.setup(&mut cmd);
 
-        let output = cmd.spawn()?.wait_with_output()?;
+        cmd
+    }
+
+    /// Render the command as it could be typed in a shell, for example when running in dry-run
+    /// mode
+    fn display(self, repo: &Repo) -> String {
+        render(&self.command(repo))
+    }
+
+    fn setup(self, cmd: &mut Command);
+}
 
-        if output.status.success() {
-            Ok(output.try_into()?)
+fn render(cmd: &Command) -> String {
+    let quote = |arg: &OsStr| {
+        let arg = arg.to_string_lossy();
+        let plain = !arg.is_empty()
+            && arg
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_./:^=%@+,".contains(c));
+
+        if plain {
+            arg.into_owned()
         } else {
-            Err(Error::Status(output))
+            format!("'{}'", arg.replace('\'', "'\\''"))
         }
+    };
+
+    std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(quote)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, GitCmd, Repo, Scripted};
+
+    /// Arguments given to git by `cmd`, without the leading `-C <path>`
+    pub fn args<C: GitCmd>(cmd: C) -> Vec<String> {
+        cmd.command(&Repo::new("."))
+            .get_args()
+            .skip(2)
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
     }
 
-    fn setup(self, cmd: &mut Command);
+    #[test]
+    fn display_quotes_arguments() {
+        let repo = Repo::new("/tmp/gccrs");
+        let cmd = super::commit().amend().message("gccrs: Don't panic");
+
+        assert_eq!(
+            cmd.display(&repo),
+            "git -C /tmp/gccrs commit --amend -m 'gccrs: Don'\\''t panic'"
+        );
+    }
+
+    #[tokio::test]
+    async fn spawn_async_checks_status() {
+        let runner = Scripted::default().ok("a1b2c3d").fail(
+            128,
+            "",
+            "fatal: 'gcc' does not appear to be a git repository",
+        );
+        let repo = Repo::with_runner("gccrs", runner);
+
+        let out = super::log().amount(1).spawn_async(&repo).await.unwrap();
+        assert_eq!(out.stdout, "a1b2c3d");
+
+        let err = super::fetch().remote("gcc").spawn_async(&repo).await;
+        assert!(matches!(err, Err(Error::UnknownRemote(r)) if r == "gcc"));
+    }
+
+    #[test]
+    fn classify_cherry_pick_conflict() {
+        let stdout = "Auto-merging gcc/rust/lex/rust-lex.cc\n\
+                      CONFLICT (content): Merge conflict in gcc/rust/lex/rust-lex.cc";
+        let stderr = "error: could not apply 1a2b3c4... lexer: Fix token spans\n\
+                      hint: after resolving the conflicts, mark the corrected paths";
+
+        match Error::classify(Some(1), stdout, stderr) {
+            Error::CherryPickConflict { commit, files } => {
+                assert_eq!(commit.as_deref(), Some("1a2b3c4"));
+                assert_eq!(files, vec!["gcc/rust/lex/rust-lex.cc"]);
+            }
+            e => panic!("unexpected error: {e:?}"),
+        }
+    }
+
+    #[test]
+    fn classify_unknown_remote() {
+        let stderr = "fatal: 'upstream' does not appear to be a git repository\n\
+                      fatal: Could not read from remote repository.";
+
+        assert!(matches!(
+            Error::classify(Some(128), "", stderr),
+            Error::UnknownRemote(remote) if remote == "upstream"
+        ));
+    }
+
+    #[test]
+    fn classify_missing_ref() {
+        let stderr = "fatal: ambiguous argument 'gcc/trunk': unknown revision or path not in the working tree.";
+
+        assert!(matches!(
+            Error::classify(Some(128), "", stderr),
+            Error::MissingRef(r) if r == "gcc/trunk"
+        ));
+        assert!(matches!(
+            Error::classify(Some(128), "", "fatal: invalid reference: prepare-2023"),
+            Error::MissingRef(r) if r == "prepare-2023"
+        ));
+    }
+
+    #[test]
+    fn classify_push_failures() {
+        let stderr = "To github.com:cohenarthur/gccrs.git\n \
+                      ! [rejected]        HEAD -> prepare (non-fast-forward)\n\
+                      error: failed to push some refs to 'github.com:cohenarthur/gccrs.git'";
+
+        assert!(matches!(
+            Error::classify(Some(1), "", stderr),
+            Error::NonFastForward(r) if r == "HEAD -> prepare"
+        ));
+
+        let stderr = "remote: Invalid username or password.\n\
+                      fatal: Authentication failed for 'https://github.com/rust-gcc/gccrs.git/'";
+
+        assert!(matches!(
+            Error::classify(Some(128), "", stderr),
+            Error::Auth(_)
+        ));
+    }
+
+    #[test]
+    fn classify_nothing_to_commit() {
+        let stdout = "On branch prepare\nnothing to commit, working tree clean";
+
+        assert!(matches!(
+            Error::classify(Some(1), stdout, ""),
+            Error::NothingToCommit
+        ));
+        assert!(matches!(
+            Error::classify(Some(129), "", "usage: git log"),
+            Error::Status {
+                code: Some(129),
+                ..
+            }
+        ));
+    }
 }
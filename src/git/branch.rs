@@ -11,6 +11,8 @@ pub enum StartingPoint<T: Into<String>> {
 #[derive(Default)]
 pub struct Branch {
     name: Option<String>,
+    force: bool,
+    force_delete: bool,
     starting_point: Option<String>,
 }
 
@@ -26,6 +28,22 @@ impl Branch {
         }
     }
 
+    /// Reset the branch to the starting point if it already exists
+    pub fn force(self) -> Branch {
+        Branch {
+            force: true,
+            ..self
+        }
+    }
+
+    /// Delete the branch, even if it has not been merged
+    pub fn force_delete(self) -> Branch {
+        Branch {
+            force_delete: true,
+            ..self
+        }
+    }
+
     pub fn starting_point<T: Into<String>>(self, starting_point: StartingPoint<T>) -> Branch {
         Branch {
             starting_point: Some(match starting_point {
@@ -42,6 +60,13 @@ impl GitCmd for Branch {
     fn setup(self, cmd: &mut Command) {
         cmd.arg("branch");
 
+        if self.force {
+            cmd.arg("--force");
+        }
+        if self.force_delete {
+            cmd.arg("-D");
+        }
+
         self.name.map(|n| cmd.arg(n));
         self.starting_point.map(|s| cmd.arg(s));
     }
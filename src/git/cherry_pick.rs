@@ -4,17 +4,58 @@ use super::{Commit, GitCmd};
 
 #[derive(Default)]
 pub struct CherryPick {
-    commit: String,
+    commit: Option<String>,
+    abort: bool,
+    strategy: Option<String>,
+    strategy_options: Vec<String>,
 }
 
 pub fn cherry_pick<T: Into<String>>(Commit(commit): Commit<T>) -> CherryPick {
     CherryPick {
-        commit: commit.into(),
+        commit: Some(commit.into()),
+        ..CherryPick::default()
+    }
+}
+
+/// Cancel the cherry-pick in progress and go back to the pre-sequence state
+pub fn cherry_pick_abort() -> CherryPick {
+    CherryPick {
+        abort: true,
+        ..CherryPick::default()
+    }
+}
+
+impl CherryPick {
+    pub fn strategy<T: Into<String>>(self, strategy: T) -> CherryPick {
+        CherryPick {
+            strategy: Some(strategy.into()),
+            ..self
+        }
+    }
+
+    pub fn strategy_option<T: Into<String>>(self, option: T) -> CherryPick {
+        let mut strategy_options = self.strategy_options;
+        strategy_options.push(option.into());
+
+        CherryPick {
+            strategy_options,
+            ..self
+        }
     }
 }
 
 impl GitCmd for CherryPick {
     fn setup(self, cmd: &mut Command) {
-        cmd.arg("cherry-pick").arg(self.commit);
+        cmd.arg("cherry-pick");
+
+        if self.abort {
+            cmd.arg("--abort");
+        }
+
+        self.strategy.map(|s| cmd.arg(format!("--strategy={s}")));
+        self.strategy_options.iter().for_each(|option| {
+            cmd.arg(format!("--strategy-option={option}"));
+        });
+        self.commit.map(|c| cmd.arg(c));
     }
 }
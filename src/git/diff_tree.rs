@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use std::process::Command;
+
+use super::{Commit, Error, GitCmd, Repo};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Status {
+    Added,
+    Modified,
+    Deleted,
+    Renamed { from: String },
+    Copied { from: String },
+    TypeChanged,
+    Other(char),
+}
+
+/// File changed by a commit. Line counts are `None` for binary files.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChangedFile {
+    pub status: Status,
+    pub path: String,
+    pub added: Option<usize>,
+    pub deleted: Option<usize>,
+}
+
+#[cfg(test)]
+impl ChangedFile {
+    /// File with one line added, for tests which do not look at the line counts
+    pub fn new<T: Into<String>>(status: Status, path: T) -> ChangedFile {
+        ChangedFile {
+            status,
+            path: path.into(),
+            added: Some(1),
+            deleted: Some(0),
+        }
+    }
+
+    pub fn lines(self, added: Option<usize>, deleted: Option<usize>) -> ChangedFile {
+        ChangedFile {
+            added,
+            deleted,
+            ..self
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct DiffTree {
+    commit: String,
+    name_only: bool,
+    changes: bool,
+    dirs: Vec<String>,
+}
+
+pub fn diff_tree<T: Into<String>>(Commit(commit): Commit<T>) -> DiffTree {
+    DiffTree {
+        commit: commit.into(),
+        ..DiffTree::default()
+    }
+}
+
+impl DiffTree {
+    pub fn name_only(self) -> DiffTree {
+        DiffTree {
+            name_only: true,
+            ..self
+        }
+    }
+
+    pub fn dir<T: Into<String>>(self, to_add: T) -> DiffTree {
+        let mut dirs = self.dirs;
+        dirs.push(to_add.into());
+
+        DiffTree { dirs, ..self }
+    }
+
+    pub fn dirs<T: Into<String>>(self, dirs: Vec<T>) -> DiffTree {
+        DiffTree {
+            dirs: dirs.into_iter().map(Into::into).collect(),
+            ..self
+        }
+    }
+
+    fn changes(self) -> DiffTree {
+        DiffTree {
+            changes: true,
+            name_only: false,
+            ..self
+        }
+    }
+
+    /// Fetch the list of files changed by the commit, with their status and line counts
+    pub fn files(self, repo: &Repo) -> Result<Vec<ChangedFile>, Error> {
+        Ok(parse(&self.changes().spawn(repo)?.stdout))
+    }
+
+    /// Asynchronous version of [`DiffTree::files`]
+    pub async fn files_async(self, repo: &Repo) -> Result<Vec<ChangedFile>, Error> {
+        Ok(parse(&self.changes().spawn_async(repo).await?.stdout))
+    }
+}
+
+/// Parse the output of `git diff-tree -z --raw --numstat`: all the raw records come first, then
+/// the numstat ones, whose counts are matched to the raw records by path. Paths are separated by
+/// NUL bytes and never quoted, and renamed or copied files have both their old and new paths.
+fn parse(output: &str) -> Vec<ChangedFile> {
+    let mut fields = output.split('\0');
+    let mut raw = Vec::new();
+    let mut counts = HashMap::new();
+
+    while let Some(field) = fields.next() {
+        if let Some(meta) = field.strip_prefix(':') {
+            // :100644 100644 bcd1234 0123456 R086\0old/path\0new/path
+            let Some(status) = meta.split_whitespace().nth(4) else {
+                continue;
+            };
+            let Some(path) = fields.next() else {
+                break;
+            };
+
+            let (status, path) = match status.chars().next().unwrap_or_default() {
+                'A' => (Status::Added, path),
+                'M' => (Status::Modified, path),
+                'D' => (Status::Deleted, path),
+                'T' => (Status::TypeChanged, path),
+                'R' => match fields.next() {
+                    Some(to) => (Status::Renamed { from: path.into() }, to),
+                    None => break,
+                },
+                'C' => match fields.next() {
+                    Some(to) => (Status::Copied { from: path.into() }, to),
+                    None => break,
+                },
+                c => (Status::Other(c), path),
+            };
+
+            raw.push((status, path.to_string()));
+        } else if let Some((added, rest)) = field.split_once('\t') {
+            // 1\t0\tpath, or 1\t0\t\0old/path\0new/path for renamed and copied files
+            let Some((deleted, path)) = rest.split_once('\t') else {
+                continue;
+            };
+            let path = match path {
+                "" => match fields.nth(1) {
+                    Some(path) => path,
+                    None => break,
+                },
+                path => path,
+            };
+
+            counts.insert(path, (added.parse().ok(), deleted.parse().ok()));
+        }
+    }
+
+    raw.into_iter()
+        .map(|(status, path)| {
+            let (added, deleted) = counts.get(path.as_str()).copied().unwrap_or_default();
+
+            ChangedFile {
+                status,
+                path,
+                added,
+                deleted,
+            }
+        })
+        .collect()
+}
+
+impl GitCmd for DiffTree {
+    fn setup(self, cmd: &mut Command) {
+        cmd.arg("diff-tree").arg("--no-commit-id").arg("-r");
+
+        if self.name_only {
+            cmd.arg("--name-only");
+        }
+        if self.changes {
+            cmd.arg("--root")
+                .arg("-M")
+                .arg("-z")
+                .arg("--raw")
+                .arg("--numstat");
+        }
+
+        cmd.arg(self.commit);
+
+        if !self.dirs.is_empty() {
+            cmd.arg("--");
+        }
+        self.dirs.iter().for_each(|dir| {
+            cmd.arg(dir);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::{tests::args, Scripted};
+
+    #[test]
+    fn setup() {
+        let cmd = diff_tree(Commit("a1b2c3d")).name_only().dir("gcc/rust");
+
+        assert_eq!(
+            args(cmd),
+            [
+                "diff-tree",
+                "--no-commit-id",
+                "-r",
+                "--name-only",
+                "a1b2c3d",
+                "--",
+                "gcc/rust"
+            ]
+        );
+    }
+
+    #[test]
+    fn files() {
+        let output = ":000000 100644 0000000 257cc56 A\0gcc/rust/bar.cc\0\
+                      :100644 100644 1f2e3d4 5a6b7c8 M\0gcc/rust/Make-lang.in\0\
+                      :100644 000000 9a8b7c6 0000000 D\0gcc/rust/old.h\0\
+                      :100644 100644 1234567 89abcde R092\0gcc/rust/a.cc\0gcc/rust/caf\u{e9}.cc\0\
+                      :100644 100644 1234567 89abcde M\0libgrust/logo.png\0\
+                      :000000 100644 0000000 257cc56 A\0gcc/rust/tab\tfile.cc\0\
+                      1\t0\tgcc/rust/bar.cc\0\
+                      3\t2\tgcc/rust/Make-lang.in\0\
+                      0\t12\tgcc/rust/old.h\0\
+                      4\t1\t\0gcc/rust/a.cc\0gcc/rust/caf\u{e9}.cc\0\
+                      -\t-\tlibgrust/logo.png\0\
+                      7\t0\tgcc/rust/tab\tfile.cc\0";
+        let repo = Repo::with_runner("gccrs", Scripted::default().ok(output));
+
+        let files = diff_tree(Commit("a1b2c3d")).files(&repo).unwrap();
+
+        assert_eq!(
+            files[0],
+            ChangedFile {
+                status: Status::Added,
+                path: "gcc/rust/bar.cc".into(),
+                added: Some(1),
+                deleted: Some(0),
+            }
+        );
+        assert_eq!(files[1].status, Status::Modified);
+        assert_eq!(files[2].deleted, Some(12));
+        assert_eq!(
+            files[3],
+            ChangedFile {
+                status: Status::Renamed {
+                    from: "gcc/rust/a.cc".into()
+                },
+                path: "gcc/rust/caf\u{e9}.cc".into(),
+                added: Some(4),
+                deleted: Some(1),
+            }
+        );
+        assert_eq!(files[4].added, None);
+        // counts are matched by path, so the rename does not shift them, and paths are not quoted
+        assert_eq!(files[5].path, "gcc/rust/tab\tfile.cc");
+        assert_eq!(files[5].added, Some(7));
+    }
+
+    #[test]
+    fn unknown_status() {
+        let output = ":100644 100644 1234567 89abcde X\0gcc/rust/bar.cc\0\
+                      :100644 100644 1f2e3d4 5a6b7c8 M\0gcc/rust/baz.cc\0\
+                      3\t2\tgcc/rust/baz.cc\0";
+        let repo = Repo::with_runner("gccrs", Scripted::default().ok(output));
+
+        let files = diff_tree(Commit("a1b2c3d")).files(&repo).unwrap();
+
+        assert_eq!(files[0].status, Status::Other('X'));
+        assert_eq!(files[0].added, None);
+        assert_eq!(files[1].added, Some(3));
+    }
+}
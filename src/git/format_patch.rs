@@ -0,0 +1,195 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+use super::{Error, GitCmd, Repo};
+
+#[derive(Clone, Default)]
+pub struct FormatPatch {
+    range: String,
+    commits: Vec<String>,
+    output_directory: Option<PathBuf>,
+    cover_letter: bool,
+    numbered: bool,
+    thread: bool,
+    to: Vec<String>,
+    cc: Vec<String>,
+}
+
+/// Create one patch file per commit in `range`, e.g. `gcc/trunk..HEAD`
+pub fn format_patch<T: Into<String>>(range: T) -> FormatPatch {
+    FormatPatch {
+        range: range.into(),
+        ..FormatPatch::default()
+    }
+}
+
+/// Create one patch file per commit in `commits`, in the given order rather than in the order of
+/// the history
+pub fn format_patch_commits<I, T>(commits: I) -> FormatPatch
+where
+    I: IntoIterator<Item = T>,
+    T: Into<String>,
+{
+    FormatPatch {
+        commits: commits.into_iter().map(Into::into).collect(),
+        ..FormatPatch::default()
+    }
+}
+
+impl FormatPatch {
+    pub fn output_directory<T: Into<PathBuf>>(self, dir: T) -> FormatPatch {
+        FormatPatch {
+            output_directory: Some(dir.into()),
+            ..self
+        }
+    }
+
+    /// Generate a cover letter template, numbered 0 in the series
+    pub fn cover_letter(self) -> FormatPatch {
+        FormatPatch {
+            cover_letter: true,
+            ..self
+        }
+    }
+
+    /// Use `[PATCH n/m]` subjects, even for a single patch
+    pub fn numbered(self) -> FormatPatch {
+        FormatPatch {
+            numbered: true,
+            ..self
+        }
+    }
+
+    /// Make every patch a reply to the cover letter, or to the first patch
+    pub fn thread(self) -> FormatPatch {
+        FormatPatch {
+            thread: true,
+            ..self
+        }
+    }
+
+    pub fn to<T: Into<String>>(self, address: T) -> FormatPatch {
+        let mut to = self.to;
+        to.push(address.into());
+
+        FormatPatch { to, ..self }
+    }
+
+    pub fn cc<T: Into<String>>(self, address: T) -> FormatPatch {
+        let mut cc = self.cc;
+        cc.push(address.into());
+
+        FormatPatch { cc, ..self }
+    }
+
+    /// Write the patches, and return the paths of the files created in order
+    pub async fn files_async(self, repo: &Repo) -> Result<Vec<PathBuf>, Error> {
+        Ok(self
+            .spawn_async(repo)
+            .await?
+            .stdout
+            .lines()
+            .map(PathBuf::from)
+            .collect())
+    }
+}
+
+impl GitCmd for FormatPatch {
+    fn setup(self, cmd: &mut Command) {
+        cmd.arg("format-patch");
+
+        self.output_directory
+            .map(|dir| cmd.arg("--output-directory").arg(dir));
+        if self.cover_letter {
+            cmd.arg("--cover-letter");
+        }
+        if self.numbered {
+            cmd.arg("--numbered");
+        }
+        if self.thread {
+            cmd.arg("--thread=shallow");
+        }
+        self.to.iter().for_each(|to| {
+            cmd.arg(format!("--to={to}"));
+        });
+        self.cc.iter().for_each(|cc| {
+            cmd.arg(format!("--cc={cc}"));
+        });
+
+        if self.commits.is_empty() {
+            cmd.arg(self.range);
+        } else {
+            // format-patch writes the commits it is given in reverse order, as it does for the
+            // newest-first history it walks
+            cmd.arg("--no-walk=unsorted")
+                .args(self.commits.iter().rev());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::{tests::args, Scripted};
+
+    #[test]
+    fn setup() {
+        let cmd = format_patch("a1b2c3d..HEAD")
+            .output_directory("patches")
+            .cover_letter()
+            .numbered()
+            .thread()
+            .to("gcc-patches@gcc.gnu.org")
+            .cc("gcc-rust@gcc.gnu.org");
+
+        assert_eq!(
+            args(cmd),
+            [
+                "format-patch",
+                "--output-directory",
+                "patches",
+                "--cover-letter",
+                "--numbered",
+                "--thread=shallow",
+                "--to=gcc-patches@gcc.gnu.org",
+                "--cc=gcc-rust@gcc.gnu.org",
+                "a1b2c3d..HEAD"
+            ]
+        );
+    }
+
+    #[test]
+    fn commits() {
+        let cmd = format_patch_commits(["2f2cf24", "e2c13b1"]).numbered();
+
+        assert_eq!(
+            args(cmd),
+            [
+                "format-patch",
+                "--numbered",
+                "--no-walk=unsorted",
+                "e2c13b1",
+                "2f2cf24"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn files() {
+        let output = "patches/0000-cover-letter.patch\npatches/0001-gccrs-Add-bar.patch";
+        let repo = Repo::with_runner("gccrs", Scripted::default().ok(output));
+
+        let files = format_patch("a1b2c3d..HEAD")
+            .files_async(&repo)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            files,
+            [
+                PathBuf::from("patches/0000-cover-letter.patch"),
+                PathBuf::from("patches/0001-gccrs-Add-bar.patch")
+            ]
+        );
+    }
+}
@@ -56,3 +56,22 @@ impl GitCmd for Log {
         self.branch.map(|b| cmd.arg(b));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::git::{self, tests::args, Branch, Format};
+
+    #[test]
+    fn setup() {
+        let cmd = git::log()
+            .amount(1)
+            .grep("gccrs: ")
+            .branch(Branch("gcc/trunk"))
+            .format(Format::Title);
+
+        assert_eq!(
+            args(cmd),
+            ["log", "-1", "--grep", "gccrs: ", "--format=%s", "gcc/trunk"]
+        );
+    }
+}
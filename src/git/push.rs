@@ -5,6 +5,7 @@ use super::{Branch, GitCmd, Remote};
 #[derive(Default)]
 pub struct Push {
     upstream: Option<String>,
+    force_with_lease: Option<(String, String)>,
     refspecs: Vec<String>,
 }
 
@@ -20,6 +21,18 @@ impl Push {
         }
     }
 
+    /// Overwrite `refname` on the remote, but only if it still points to `expected`
+    pub fn force_with_lease<T1: Into<String>, T2: Into<String>>(
+        self,
+        refname: T1,
+        expected: T2,
+    ) -> Push {
+        Push {
+            force_with_lease: Some((refname.into(), expected.into())),
+            ..self
+        }
+    }
+
     pub fn branch<T: Into<String>>(self, Branch(branch): Branch<T>) -> Push {
         let mut refspecs = self.refspecs;
         refspecs.push(branch.into());
@@ -32,9 +45,44 @@ impl GitCmd for Push {
     fn setup(self, cmd: &mut Command) {
         cmd.arg("push");
 
+        self.force_with_lease
+            .map(|(refname, expected)| cmd.arg(format!("--force-with-lease={refname}:{expected}")));
         self.upstream.map(|remote| cmd.arg("-u").arg(remote));
         self.refspecs.iter().for_each(|r| {
             cmd.arg(r);
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::git::{self, tests::args, Branch, Remote};
+
+    #[test]
+    fn setup() {
+        let cmd = git::push()
+            .upstream(Remote("origin"))
+            .branch(Branch("HEAD"));
+
+        assert_eq!(args(cmd), ["push", "-u", "origin", "HEAD"]);
+    }
+
+    #[test]
+    fn force_with_lease() {
+        let cmd = git::push()
+            .force_with_lease("refs/heads/prepare", "a1b2c3d")
+            .upstream(Remote("origin"))
+            .branch(Branch("HEAD:refs/heads/prepare"));
+
+        assert_eq!(
+            args(cmd),
+            [
+                "push",
+                "--force-with-lease=refs/heads/prepare:a1b2c3d",
+                "-u",
+                "origin",
+                "HEAD:refs/heads/prepare"
+            ]
+        );
+    }
+}
@@ -1,13 +1,28 @@
-use super::{Branch, GitCmd};
+use super::{Branch, Error, GitCmd, Repo};
 
 use std::process::Command;
 
+/// Mark given by `--cherry-mark` to each listed commit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mark {
+    /// No commit with the same patch exists on the other side of the range (`+`)
+    Unique,
+    /// A commit with the same patch exists on the other side of the range (`=`)
+    Equivalent,
+}
+
 #[derive(Default)]
 pub struct RevList {
+    /// Range given as is to git, instead of `start` and `end`
+    range: Option<String>,
     start: String,
     end: String,
+    symmetric: bool,
     prevent_merges: bool,
+    only_merges: bool,
     reverse: bool,
+    right_only: bool,
+    cherry_mark: bool,
     exclude: Option<String>,
     dirs: Vec<String>,
 }
@@ -21,7 +36,32 @@ pub fn rev_list<T1: Into<String>, T2: Into<String>>(start: T1, end: T2) -> RevLi
     }
 }
 
+/// List the commits of a range written as git understands it, e.g. `A..B` or `A...B`
+pub fn rev_list_range<T: Into<String>>(range: T) -> RevList {
+    RevList {
+        range: Some(range.into()),
+        ..RevList::default()
+    }
+}
+
 impl RevList {
+    /// List the commits reachable from either end of the range but not from both, using
+    /// `start...end`
+    pub fn symmetric(self) -> RevList {
+        RevList {
+            symmetric: true,
+            ..self
+        }
+    }
+
+    /// Only list the commits on the `end` side of a symmetric range
+    pub fn right_only(self) -> RevList {
+        RevList {
+            right_only: true,
+            ..self
+        }
+    }
+
     pub fn no_merges(self) -> RevList {
         RevList {
             prevent_merges: true,
@@ -29,6 +69,14 @@ impl RevList {
         }
     }
 
+    /// Only list the merge commits
+    pub fn merges(self) -> RevList {
+        RevList {
+            only_merges: true,
+            ..self
+        }
+    }
+
     pub fn reverse(self) -> RevList {
         RevList {
             reverse: true,
@@ -56,12 +104,50 @@ impl RevList {
             ..self
         }
     }
+
+    fn cherry_mark(self) -> RevList {
+        RevList {
+            cherry_mark: true,
+            ..self
+        }
+    }
+
+    /// List the commits along with whether a commit introducing the same patch, as computed by
+    /// `git patch-id`, exists on the other side of a symmetric range
+    pub fn marks(self, repo: &Repo) -> Result<Vec<(Mark, String)>, Error> {
+        Ok(parse_marks(&self.cherry_mark().spawn(repo)?.stdout))
+    }
+
+    /// Asynchronous version of [`RevList::marks`]
+    pub async fn marks_async(self, repo: &Repo) -> Result<Vec<(Mark, String)>, Error> {
+        Ok(parse_marks(
+            &self.cherry_mark().spawn_async(repo).await?.stdout,
+        ))
+    }
+}
+
+fn parse_marks(output: &str) -> Vec<(Mark, String)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mark = match line.chars().next()? {
+                '+' => Mark::Unique,
+                '=' => Mark::Equivalent,
+                _ => return None,
+            };
+
+            Some((mark, line[1..].trim().to_string()))
+        })
+        .collect()
 }
 
 impl GitCmd for RevList {
     fn setup(self, cmd: &mut Command) {
-        cmd.arg("rev-list")
-            .arg(format!("{}..{}", self.start, self.end));
+        let dots = if self.symmetric { "..." } else { ".." };
+        cmd.arg("rev-list").arg(
+            self.range
+                .unwrap_or_else(|| format!("{}{dots}{}", self.start, self.end)),
+        );
 
         if self.reverse {
             cmd.arg("--reverse");
@@ -69,6 +155,15 @@ impl GitCmd for RevList {
         if self.prevent_merges {
             cmd.arg("--no-merges");
         }
+        if self.only_merges {
+            cmd.arg("--merges");
+        }
+        if self.right_only {
+            cmd.arg("--right-only");
+        }
+        if self.cherry_mark {
+            cmd.arg("--cherry-mark");
+        }
 
         self.exclude
             .map(|to_exclude| cmd.arg(format!("^{to_exclude}")));
@@ -81,3 +176,70 @@ impl GitCmd for RevList {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Mark;
+    use crate::git::{self, tests::args, Branch, Repo, Scripted};
+
+    #[test]
+    fn setup() {
+        let cmd = git::rev_list("a1b2c3d", "upstream/master")
+            .no_merges()
+            .reverse()
+            .exclude(Branch("gcc/trunk"))
+            .dir("gcc/rust")
+            .dir("libgrust");
+
+        assert_eq!(
+            args(cmd),
+            [
+                "rev-list",
+                "a1b2c3d..upstream/master",
+                "--reverse",
+                "--no-merges",
+                "^gcc/trunk",
+                "--",
+                "gcc/rust",
+                "libgrust"
+            ]
+        );
+    }
+
+    #[test]
+    fn range() {
+        let cmd = git::rev_list_range("gcc/trunk...HEAD").reverse();
+
+        assert_eq!(args(cmd), ["rev-list", "gcc/trunk...HEAD", "--reverse"]);
+    }
+
+    #[test]
+    fn marks() {
+        let runner = Scripted::default().ok("=3927179\n+92f0038\n+4002603");
+        let repo = Repo::with_runner("gccrs", runner.clone());
+
+        let marks = git::rev_list("gcc/trunk", "upstream/master")
+            .symmetric()
+            .right_only()
+            .marks(&repo)
+            .unwrap();
+
+        assert_eq!(
+            marks,
+            [
+                (Mark::Equivalent, "3927179".to_string()),
+                (Mark::Unique, "92f0038".to_string()),
+                (Mark::Unique, "4002603".to_string()),
+            ]
+        );
+        assert_eq!(
+            runner.calls()[0][2..],
+            [
+                "rev-list",
+                "gcc/trunk...upstream/master",
+                "--right-only",
+                "--cherry-mark"
+            ]
+        );
+    }
+}
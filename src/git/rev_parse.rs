@@ -0,0 +1,50 @@
+use std::process::Command;
+
+use super::GitCmd;
+
+#[derive(Default)]
+pub struct RevParse {
+    rev: String,
+    abbrev_ref: bool,
+    verify: bool,
+}
+
+pub fn rev_parse<T: Into<String>>(rev: T) -> RevParse {
+    RevParse {
+        rev: rev.into(),
+        ..RevParse::default()
+    }
+}
+
+impl RevParse {
+    /// Output the short name of the reference, e.g. the current branch for `HEAD`
+    pub fn abbrev_ref(self) -> RevParse {
+        RevParse {
+            abbrev_ref: true,
+            ..self
+        }
+    }
+
+    /// Fail quietly if the revision does not exist
+    pub fn verify(self) -> RevParse {
+        RevParse {
+            verify: true,
+            ..self
+        }
+    }
+}
+
+impl GitCmd for RevParse {
+    fn setup(self, cmd: &mut Command) {
+        cmd.arg("rev-parse");
+
+        if self.abbrev_ref {
+            cmd.arg("--abbrev-ref");
+        }
+        if self.verify {
+            cmd.arg("--quiet").arg("--verify");
+        }
+
+        cmd.arg(self.rev);
+    }
+}
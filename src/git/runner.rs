@@ -0,0 +1,147 @@
+use std::collections::VecDeque;
+use std::future::{self, Future};
+use std::io;
+use std::pin::Pin;
+use std::process::{self, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+
+pub type RunFuture = Pin<Box<dyn Future<Output = io::Result<process::Output>> + Send>>;
+
+/// Abstraction over the execution of git commands, so that the git layer can be tested without
+/// a real git installation or repository
+pub trait Runner: Send + Sync {
+    fn run(&self, cmd: Command) -> io::Result<process::Output>;
+
+    /// Execute the command without blocking the async runtime
+    fn run_async(&self, cmd: Command) -> RunFuture;
+}
+
+/// Runner which spawns the command as a child process and waits for it to complete
+pub struct Process;
+
+impl Runner for Process {
+    fn run(&self, mut cmd: Command) -> io::Result<process::Output> {
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+
+        cmd.spawn()?.wait_with_output()
+    }
+
+    fn run_async(&self, cmd: Command) -> RunFuture {
+        let mut cmd = tokio::process::Command::from(cmd);
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+
+        Box::pin(async move { cmd.spawn()?.wait_with_output().await })
+    }
+}
+
+#[derive(Default)]
+struct Script {
+    outputs: VecDeque<process::Output>,
+    calls: Vec<Vec<String>>,
+}
+
+/// Fake runner which records the arguments of each command it receives, and answers with the
+/// outputs it was given in order. Once all scripted outputs have been consumed, commands succeed
+/// with an empty output.
+#[derive(Clone, Default)]
+pub struct Scripted {
+    script: Arc<Mutex<Script>>,
+}
+
+#[cfg(unix)]
+fn exit_status(code: i32) -> ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+
+    ExitStatus::from_raw(code << 8)
+}
+
+#[cfg(windows)]
+fn exit_status(code: i32) -> ExitStatus {
+    use std::os::windows::process::ExitStatusExt;
+
+    ExitStatus::from_raw(code as u32)
+}
+
+impl Scripted {
+    fn push(self, code: i32, stdout: String, stderr: String) -> Scripted {
+        self.script
+            .lock()
+            .unwrap()
+            .outputs
+            .push_back(process::Output {
+                status: exit_status(code),
+                stdout: stdout.into_bytes(),
+                stderr: stderr.into_bytes(),
+            });
+
+        self
+    }
+
+    /// Answer the next command with a successful exit status and `stdout`
+    pub fn ok<T: Into<String>>(self, stdout: T) -> Scripted {
+        self.push(0, stdout.into(), String::new())
+    }
+
+    /// Answer the next command with the exit status `code` and the given output
+    pub fn fail<T1: Into<String>, T2: Into<String>>(
+        self,
+        code: i32,
+        stdout: T1,
+        stderr: T2,
+    ) -> Scripted {
+        self.push(code, stdout.into(), stderr.into())
+    }
+
+    /// Arguments of all the commands received so far, without the program name
+    pub fn calls(&self) -> Vec<Vec<String>> {
+        self.script.lock().unwrap().calls.clone()
+    }
+}
+
+impl Runner for Scripted {
+    fn run(&self, cmd: Command) -> io::Result<process::Output> {
+        let mut script = self.script.lock().unwrap();
+
+        script.calls.push(
+            cmd.get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+        );
+
+        Ok(script.outputs.pop_front().unwrap_or(process::Output {
+            status: exit_status(0),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+        }))
+    }
+
+    fn run_async(&self, cmd: Command) -> RunFuture {
+        Box::pin(future::ready(self.run(cmd)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scripted_records_calls() {
+        let runner = Scripted::default()
+            .ok("a1b2c3d")
+            .fail(128, "", "fatal: oops");
+
+        let mut cmd = Command::new("git");
+        cmd.args(["log", "-1"]);
+        let out = runner.run(cmd).unwrap();
+        assert!(out.status.success());
+        assert_eq!(out.stdout, b"a1b2c3d");
+
+        let out = runner.run(Command::new("git")).unwrap();
+        assert_eq!(out.status.code(), Some(128));
+
+        let out = runner.run(Command::new("git")).unwrap();
+        assert!(out.status.success());
+
+        assert_eq!(runner.calls(), vec![vec!["log", "-1"], vec![], vec![]]);
+    }
+}
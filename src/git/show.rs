@@ -0,0 +1,34 @@
+use std::process::Command;
+
+use super::{Commit, Format, GitCmd};
+
+pub struct Show {
+    commit: String,
+    format: Option<Format>,
+}
+
+pub fn show<T: Into<String>>(Commit(commit): Commit<T>) -> Show {
+    Show {
+        commit: commit.into(),
+        format: None,
+    }
+}
+
+impl Show {
+    pub fn format(self, format: Format) -> Show {
+        Show {
+            format: Some(format),
+            ..self
+        }
+    }
+}
+
+impl GitCmd for Show {
+    fn setup(self, cmd: &mut Command) {
+        cmd.arg("show").arg("-1");
+
+        self.format
+            .map(|f| cmd.arg(format!("--format={}", f.as_str())));
+        cmd.arg(self.commit);
+    }
+}
@@ -0,0 +1,23 @@
+use std::process::Command;
+
+use super::{Error, GitCmd, Repo};
+
+#[derive(Default)]
+pub struct Status;
+
+pub fn status() -> Status {
+    Status
+}
+
+impl Status {
+    /// Whether the work tree and the index have no changes nor untracked files
+    pub fn clean(self, repo: &Repo) -> Result<bool, Error> {
+        Ok(self.spawn(repo)?.stdout.is_empty())
+    }
+}
+
+impl GitCmd for Status {
+    fn setup(self, cmd: &mut Command) {
+        cmd.arg("status").arg("--porcelain");
+    }
+}
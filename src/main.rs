@@ -1,45 +1,198 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use clap::{Parser, Subcommand};
 
+use config::{or_config, Config};
+
 // FIXME: Add env_logger, would fit quite nicely here
 // FIXME: Or should we? Is the goal to compile it asap using gccrs?
 // FIXME: If not, use nom instead of the hand-written combinator
 
 mod clog;
+mod config;
 pub mod git;
 mod parser;
+mod token;
 mod upstream;
 
 #[derive(Clone, Subcommand)]
 enum SubCmd {
     /// Check the output of GCC's changelog checker (`contrib/gcc-changelog/git_check_commit.py`)
-    /// on a range of commit and post a message on GitHub indicating the necessary changes. This
-    /// subcommand takes the output of the above mentioned script as input on `stdin`.
-    ChangeLogs,
+    /// on a range of commit and print the necessary changes, or post them as a comment on the
+    /// pull-request given with `--pr`. This subcommand runs the above mentioned script on
+    /// `--range`, or gerris' own checks when the repository does not contain it, or takes the
+    /// script's output as input on `stdin`. With `--fix`, the mechanical errors of
+    /// the commits of `--range` are fixed by rewriting their messages instead.
+    ChangeLogs(Box<ChangeLogsArgs>),
     /// Create a PR on `gccrs`'s repository containing the commits from master which haven't yet
     /// been formatted properly for upstreaming.
-    Upstream {
-        #[arg(short, long, help = "GitHub token to perform actions as gerris")]
-        token: Option<String>,
-
-        #[arg(
-            long,
-            help = "branch on which to base the pull-request gerris will create"
-        )]
-        to: String,
-
-        #[arg(
-            short,
-            long,
-            help = "work directory which contains a copy of the gccrs respository"
-        )]
-        work: PathBuf,
-    },
+    Upstream(Box<UpstreamArgs>),
+}
+
+#[derive(Clone, clap::Args)]
+struct ChangeLogsArgs {
+    #[arg(
+        long,
+        value_name = "A..B",
+        help = "range of commits to check, with GCC's ChangeLog checker if the current repository contains it or with gerris' own checks otherwise, instead of reading the checker's output from stdin"
+    )]
+    range: Option<String>,
+
+    #[arg(
+        long,
+        requires = "range",
+        help = "GCC repository containing the commits and GCC's checker, if it is not the current directory"
+    )]
+    gcc_dir: Option<PathBuf>,
+
+    #[command(flatten)]
+    scripts: config::ChangeLogs,
+
+    #[arg(
+        long,
+        requires = "range",
+        conflicts_with_all = ["gcc_dir", "pr"],
+        help = "rewrite the messages of the commits of `--range`, which must end at the current commit, to fix their mechanical ChangeLog errors"
+    )]
+    fix: bool,
+
+    #[arg(
+        long,
+        requires = "fix",
+        help = "rewrite the commits without asking for confirmation after showing the changes"
+    )]
+    yes: bool,
+
+    #[arg(
+        long,
+        value_name = "NUMBER",
+        help = "pull-request on which to comment with the results, instead of printing them"
+    )]
+    pr: Option<u64>,
+
+    #[arg(
+        long,
+        help = "GitHub repository of the pull-request, as `owner/name` [default: rust-gcc/gccrs]"
+    )]
+    repo: Option<upstream::GithubRepo>,
+
+    #[command(flatten)]
+    token: token::TokenArgs,
+}
+
+#[derive(Clone, clap::Args)]
+struct UpstreamArgs {
+    #[command(flatten)]
+    token: token::TokenArgs,
+
+    #[arg(
+        long,
+        help = "branch on which to base the pull-request gerris will create"
+    )]
+    to: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        help = "work directory which contains a copy of the gccrs respository"
+    )]
+    work: PathBuf,
+
+    #[arg(
+        long,
+        help = "only print the commands modifying the repository and the pull-request which would be created"
+    )]
+    dry_run: bool,
+
+    #[arg(
+        long,
+        help = "directory maintained by gccrs, from which commits are upstreamed [default: gcc/rust, gcc/testsuite/rust, libgrust]"
+    )]
+    dir: Vec<String>,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "what to do when a commit cannot be cherry-picked without conflicts [default: stop]"
+    )]
+    on_conflict: Option<upstream::OnConflict>,
+
+    #[arg(
+        long,
+        help = "merge strategy with which to retry conflicting cherry-picks"
+    )]
+    strategy: Option<String>,
+
+    #[arg(
+        short = 'X',
+        long,
+        help = "option to pass to the merge strategy when retrying conflicting cherry-picks"
+    )]
+    strategy_option: Vec<String>,
+
+    #[arg(
+        long = "continue",
+        conflicts_with = "abort",
+        help = "continue the preparation in progress after fixing a conflict by hand"
+    )]
+    r#continue: bool,
+
+    #[arg(
+        long,
+        help = "abort the preparation in progress, restoring the original branch"
+    )]
+    abort: bool,
+
+    #[command(flatten)]
+    remotes: config::Remotes,
+
+    #[command(flatten)]
+    pull_request: config::PullRequest,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "write the prepared commits as a patch series for gcc-patches in this directory, instead of opening a pull-request"
+    )]
+    format_patch: Option<PathBuf>,
+
+    #[arg(
+        long,
+        requires = "format_patch",
+        help = "send the patch series once it is written, or only write the messages to `series.mbox` with `--dry-run`"
+    )]
+    send: bool,
+
+    #[command(flatten)]
+    mail: config::Mail,
+
+    #[command(flatten)]
+    changelogs: config::ChangeLogs,
+
+    #[arg(
+        long,
+        overrides_with = "no_strict",
+        help = "refuse to push or send the prepared commits if any of them fails GCC's ChangeLog checks"
+    )]
+    strict: bool,
+
+    #[arg(
+        long,
+        overrides_with = "strict",
+        help = "push or send the prepared commits even if `strict` is set in the configuration file"
+    )]
+    no_strict: bool,
 }
 
 #[derive(Parser)]
 struct Args {
+    #[arg(
+        long,
+        global = true,
+        help = "configuration file to use instead of the `gerris.toml` found in the work directory"
+    )]
+    config: Option<PathBuf>,
+
     #[command(subcommand)]
     cmd: SubCmd,
 }
@@ -50,12 +203,90 @@ async fn main() -> anyhow::Result<()> {
     env_logger::init();
 
     match args.cmd {
-        SubCmd::ChangeLogs => clog::check_clog_checker_output()?,
-        SubCmd::Upstream { token, to, work } => {
-            upstream::prepare_commits(upstream::UpstreamOpt {
+        SubCmd::ChangeLogs(cli) => {
+            let config = Config::load(args.config.as_deref(), Path::new("."))?;
+            if let (true, Some(range)) = (cli.fix, &cli.range) {
+                return Ok(clog::fix::fix_range(range, cli.yes)?);
+            }
+
+            let opt = cli.scripts.or(config.changelogs).resolve();
+            // only needed to comment, but better to fail before running the checks
+            let token = match cli.pr {
+                Some(_) => Some(cli.token.load()?.ok_or(clog::Error::MissingToken)?),
+                None => None,
+            };
+
+            let reports = match (cli.range, cli.gcc_dir) {
+                (Some(range), Some(gcc_dir)) => {
+                    clog::check_clog_checker_range(&opt, &gcc_dir, &range)?
+                }
+                (Some(range), None) => clog::check_clog_range(&opt, &range)?,
+                (None, _) => clog::check_clog_checker_output(&opt)?,
+            };
+
+            match (cli.pr, token) {
+                (Some(pr), Some(token)) => {
+                    let repo = cli
+                        .repo
+                        .or(config.upstream.remotes.repo)
+                        .unwrap_or_else(|| upstream::Remotes::default().repo);
+
+                    clog::github::post(token, &repo, pr, &reports).await?
+                }
+                _ => clog::print_reports(&reports),
+            }
+        }
+        SubCmd::Upstream(cli) => {
+            let UpstreamArgs {
                 token,
-                branch: to,
+                to,
+                work,
+                dry_run,
+                dir,
+                on_conflict,
+                strategy,
+                strategy_option,
+                r#continue,
+                abort,
+                remotes,
+                pull_request,
+                format_patch,
+                send,
+                mail,
+                changelogs,
+                strict,
+                no_strict,
+            } = *cli;
+            let Config {
+                upstream: config,
+                changelogs: changelogs_config,
+            } = Config::load(args.config.as_deref(), &work)?;
+            let dirs = match or_config(dir, config.dirs) {
+                dirs if dirs.is_empty() => upstream::GCCRS_DIRS.map(String::from).to_vec(),
+                dirs => dirs,
+            };
+
+            upstream::prepare_commits(upstream::UpstreamOpt {
+                token: token.load()?,
+                branch: to.or(config.to),
                 gccrs: work,
+                dirs,
+                dry_run,
+                on_conflict: on_conflict.or(config.on_conflict).unwrap_or_default(),
+                strategy: strategy.or(config.strategy),
+                strategy_options: or_config(strategy_option, config.strategy_options),
+                resume: match (r#continue, abort) {
+                    (true, _) => upstream::Resume::Continue,
+                    (_, true) => upstream::Resume::Abort,
+                    _ => upstream::Resume::Start,
+                },
+                remotes: remotes.or(config.remotes).resolve(),
+                pull_request: pull_request.or(config.pull_request),
+                format_patch,
+                send,
+                mail: mail.or(config.mail),
+                changelogs: changelogs.or(changelogs_config).resolve(),
+                strict: !no_strict && (strict || config.strict),
             })
             .await?
         }
@@ -63,3 +294,15 @@ async fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::Args;
+
+    #[test]
+    fn cli() {
+        Args::command().debug_assert();
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
 pub type ParseResult<'i, T> = Result<(&'i str, T), ParseError<'i>>;
 
 #[derive(Debug)]
@@ -16,6 +18,25 @@ pub struct ParseError<'i> {
     pub combinator: Combinator,
 }
 
+impl Display for Combinator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Combinator::Custom(name) => write!(f, "{name}"),
+            Combinator::Character(c) => write!(f, "character `{c}`"),
+            Combinator::Alpha => write!(f, "alphabetic character"),
+            Combinator::Num => write!(f, "digit"),
+            Combinator::Tag(tag) => write!(f, "`{tag}`"),
+            Combinator::Whitespace => write!(f, "whitespace"),
+        }
+    }
+}
+
+impl<'i> Display for ParseError<'i> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "expected {} at `{}`", self.combinator, self.input)
+    }
+}
+
 pub fn character<'i>(c: char) -> impl FnOnce(&'i str) -> ParseResult<char> {
     move |input: &'i str| {
         if let Some(input) = input.strip_prefix(c) {
@@ -82,7 +103,7 @@ where
     }
 }
 
-pub fn whitespace(input: &str) -> Result<(&str, ()), ParseError> {
+pub fn whitespace(input: &str) -> Result<(&str, ()), ParseError<'_>> {
     if let Some(input) = input.strip_prefix(' ') {
         Ok((input, ()))
     } else {
@@ -0,0 +1,189 @@
+//! Loading of the GitHub token used by the subcommands which talk to GitHub. The token is looked
+//! up, in order, from `--token`, `--token-file`, the `GERRIS_TOKEN` and `GITHUB_TOKEN` environment
+//! variables, and finally git's credential helper for `github.com`. Prefer anything but
+//! `--token`, which shows up in `ps` output and shell history.
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use clap::Args;
+use log::{info, warn};
+use thiserror::Error;
+
+/// Environment variables in which to look for the token, in order of precedence
+const ENV_VARS: [&str; 2] = ["GERRIS_TOKEN", "GITHUB_TOKEN"];
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("could not read token file `{}`: {source}", .path.display())]
+    File { path: PathBuf, source: io::Error },
+    #[error("token file `{}` is empty", .0.display())]
+    EmptyFile(PathBuf),
+}
+
+#[derive(Args, Clone, Debug, Default)]
+pub struct TokenArgs {
+    #[arg(
+        short,
+        long,
+        help = "GitHub token to perform actions as gerris. Prefer `--token-file` or the environment"
+    )]
+    token: Option<String>,
+
+    #[arg(
+        long,
+        help = "file containing the GitHub token, instead of `GERRIS_TOKEN`, `GITHUB_TOKEN` or git's credential helper"
+    )]
+    token_file: Option<PathBuf>,
+}
+
+fn non_empty(token: String) -> Option<String> {
+    let token = token.trim();
+
+    (!token.is_empty()).then(|| token.to_string())
+}
+
+/// Ask git's credential helper for the password stored for `https://github.com`, without ever
+/// prompting the user
+fn credential_fill() -> Option<String> {
+    let mut child = Command::new("git")
+        .args(["credential", "fill"])
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .env("GIT_ASKPASS", "")
+        .env("SSH_ASKPASS", "")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    child
+        .stdin
+        .take()?
+        .write_all(b"protocol=https\nhost=github.com\n\n")
+        .ok()?;
+
+    let output = child.wait_with_output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    parse_credential(&String::from_utf8(output.stdout).ok()?)
+}
+
+fn parse_credential(output: &str) -> Option<String> {
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix("password="))
+        .map(String::from)
+        .and_then(non_empty)
+}
+
+impl TokenArgs {
+    /// Look up the token from the command line, the environment, then git's credential helper
+    pub fn load(self) -> Result<Option<String>, Error> {
+        self.load_with(|var| env::var(var).ok(), credential_fill)
+    }
+
+    fn load_with(
+        self,
+        var: impl Fn(&str) -> Option<String>,
+        credential_fill: impl FnOnce() -> Option<String>,
+    ) -> Result<Option<String>, Error> {
+        if let Some(token) = self.token.and_then(non_empty) {
+            warn!("token given on the command line: prefer `--token-file` or `GERRIS_TOKEN`");
+            return Ok(Some(token));
+        }
+
+        if let Some(path) = self.token_file {
+            return match fs::read_to_string(&path) {
+                Ok(content) => non_empty(content).map(Some).ok_or(Error::EmptyFile(path)),
+                Err(source) => Err(Error::File { path, source }),
+            };
+        }
+
+        if let Some((name, token)) = ENV_VARS
+            .iter()
+            .find_map(|name| var(name).and_then(non_empty).map(|token| (name, token)))
+        {
+            info!("using GitHub token from `{name}`");
+            return Ok(Some(token));
+        }
+
+        let token = credential_fill();
+        if token.is_some() {
+            info!("using GitHub token from git's credential helper");
+        }
+
+        Ok(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        let vars = [("GITHUB_TOKEN", "github"), ("GERRIS_TOKEN", "gerris")];
+        let helper = || Some(String::from("helper"));
+
+        let cli = TokenArgs {
+            token: Some("cli".into()),
+            token_file: Some("/nonexistent".into()),
+        };
+        assert_eq!(
+            cli.load_with(lookup(&vars), helper).unwrap().as_deref(),
+            Some("cli")
+        );
+
+        let from_env = TokenArgs::default().load_with(lookup(&vars), helper);
+        assert_eq!(from_env.unwrap().as_deref(), Some("gerris"));
+
+        let from_env = TokenArgs::default().load_with(lookup(&[("GERRIS_TOKEN", " ")]), helper);
+        assert_eq!(from_env.unwrap().as_deref(), Some("helper"));
+
+        let none = TokenArgs::default().load_with(lookup(&[]), || None);
+        assert_eq!(none.unwrap(), None);
+    }
+
+    #[test]
+    fn token_file() {
+        let path = env::temp_dir().join(format!("gerris-token-{}", std::process::id()));
+        fs::write(&path, "ghp_1234\n").unwrap();
+
+        let args = TokenArgs {
+            token: None,
+            token_file: Some(path.clone()),
+        };
+        let token = args
+            .clone()
+            .load_with(lookup(&[("GITHUB_TOKEN", "github")]), || None);
+        assert_eq!(token.unwrap().as_deref(), Some("ghp_1234"));
+
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            args.load_with(lookup(&[]), || None),
+            Err(Error::File { .. })
+        ));
+    }
+
+    #[test]
+    fn credential() {
+        let output = "protocol=https\nhost=github.com\nusername=gerris\npassword=ghp_5678\n";
+
+        assert_eq!(parse_credential(output).as_deref(), Some("ghp_5678"));
+        assert_eq!(parse_credential("protocol=https\n"), None);
+    }
+}
@@ -9,19 +9,12 @@
 // where gcc = "gcc/master";
 // where us = "github/master";
 //
-// # look at the latest commit on GCC which is ours -- has a "gccrs: " prefix
+// # shas and titles are different between GCC's upstream and us, so commits are matched using
+// # their patch-id: `--cherry-mark` marks commits whose patch already exists on GCC with `=`
+// where marks = git.rev_list(gcc, us).symmetric().right_only().cherry_mark();
 //
-// # we must look for the message of the last commit so that we can find it on our branch
-// # with this system sadly, shas are different between GCC's upstream and us
-// where last_title = git.log().grep("gccrs: ").amount(1).on(gcc).msg;
-//
-// # FIXME: how does this work if someone pushed a commit prefixed by "gccrs: " on `us`?
-// where last_title = last_title.strip_leading("gccrs: ");
-// where last_upstreamed_commit_us = git.log().grep(last_title).format(Hash).amount(2).on(us);
-//
-// # we now have the last commit on our remote which was pushed to GCC usptream
-// # we can easily generate the rev-list of commits to prepare and push
-// where rev_list = git.rev_list(last_upstreamed_commit, us);
+// # the commits to prepare and push are exactly the ones whose patch is not yet upstream
+// where rev_list = marks.filter(mark -> mark == "+");
 //
 // # let's create our branch which will contain these new prepared commits
 // where branch = git.branch("prepare-{Date.today()}").create().rebase(us);
@@ -61,10 +54,9 @@
 // git fetch upstream
 // # FIXME: The remotes need to exist already
 //
-// last_pushed_commit=$(git log -1 --grep "gccrs: " gcc/trunk --format="title")
-// last_msg = last_pushed_commit.strip_prefix("gccrs: ");
-// last_commit_us=$(git log -1 --grep $last_msg upstream/master --format="%h")
-// rev_list=$(git rev-list --no-merges $last_commit_us..upstream/master ^gcc/trunk -- gcc/rust/ libgrust/ gcc/testsuite/rust)
+// # commits already upstreamed are marked with `=`, the ones to prepare with `+`
+// marks=$(git rev-list --cherry-mark --right-only --no-merges --reverse gcc/trunk...upstream/master -- gcc/rust/ libgrust/ gcc/testsuite/rust)
+// rev_list=$(echo "$marks" | sed -n 's/^+//p')
 //
 // git checkout -b $date
 // for commit in rev_list.lines()
@@ -75,33 +67,216 @@
 
 use std::fmt::{Display, Formatter, Result as FmtResult};
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::string;
 
 use chrono::Local;
+use clap::ValueEnum;
 use log::{error, info, warn};
 use octocrab::OctocrabBuilder;
+use serde::Deserialize;
 use thiserror::Error;
 
+use crate::clog::{self, ChangeLogsOpt};
+use crate::config::{Mail, PullRequest};
 use crate::git::{self, GitCmd};
 
+mod body;
+mod github;
+mod send;
+mod state;
+
+use body::{Body, ChangeLogFailure, Prepared};
+pub use send::Encryption;
+use state::{CommitState, Entry, State};
+
+/// Directories maintained by gccrs. Changes outside of these touch on common GCC directories.
+pub const GCCRS_DIRS: [&str; 3] = ["gcc/rust", "gcc/testsuite/rust", "libgrust"];
+
+/// What to do when a commit cannot be cherry-picked without conflicts
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OnConflict {
+    /// Skip the commit and mention it in the pull-request
+    Skip,
+    /// Stop preparing commits
+    #[default]
+    Stop,
+}
+
+/// Whether to start a new preparation, or act on the one in progress
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Resume {
+    #[default]
+    Start,
+    /// Continue the preparation after a conflict was fixed by hand
+    Continue,
+    /// Restore the original branch and delete the partially prepared one
+    Abort,
+}
+
+/// GitHub repository, given as `owner/name`
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct GithubRepo {
+    pub owner: String,
+    pub name: String,
+}
+
+impl FromStr for GithubRepo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<GithubRepo, String> {
+        match s.split_once('/') {
+            Some((owner, name)) if !owner.is_empty() && !name.is_empty() && !name.contains('/') => {
+                Ok(GithubRepo {
+                    owner: owner.to_string(),
+                    name: name.to_string(),
+                })
+            }
+            _ => Err(format!("invalid repository `{s}`: expected `owner/name`")),
+        }
+    }
+}
+
+impl TryFrom<String> for GithubRepo {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<GithubRepo, String> {
+        s.parse()
+    }
+}
+
+impl Display for GithubRepo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}/{}", self.owner, self.name)
+    }
+}
+
+/// Remotes, branches and repositories involved in the upstream workflow
+#[derive(Clone, Debug)]
+pub struct Remotes {
+    pub upstream_remote: String,
+    pub upstream_branch: String,
+    pub gcc_remote: String,
+    pub gcc_branch: String,
+    pub push_remote: String,
+    pub repo: GithubRepo,
+    pub fork_owner: String,
+}
+
+impl Remotes {
+    /// Remote-tracking branch containing the commits to upstream, e.g. `upstream/master`
+    pub fn upstream_ref(&self) -> String {
+        format!("{}/{}", self.upstream_remote, self.upstream_branch)
+    }
+
+    /// Remote-tracking branch containing GCC's history, e.g. `gcc/trunk`
+    pub fn gcc_ref(&self) -> String {
+        format!("{}/{}", self.gcc_remote, self.gcc_branch)
+    }
+}
+
+impl Default for Remotes {
+    fn default() -> Remotes {
+        Remotes {
+            upstream_remote: String::from("upstream"),
+            upstream_branch: String::from("master"),
+            gcc_remote: String::from("gcc"),
+            gcc_branch: String::from("trunk"),
+            push_remote: String::from("origin"),
+            repo: GithubRepo {
+                owner: String::from("rust-gcc"),
+                name: String::from("gccrs"),
+            },
+            fork_owner: String::from("cohenarthur"),
+        }
+    }
+}
+
 pub struct UpstreamOpt {
     pub token: Option<String>,
-    pub branch: String,
+    /// Branch on which to base the pull-request. Only needed when starting a new preparation.
+    pub branch: Option<String>,
     pub gccrs: PathBuf,
+    /// Directories maintained by gccrs, from which commits are upstreamed
+    pub dirs: Vec<String>,
+    pub dry_run: bool,
+    pub on_conflict: OnConflict,
+    /// Merge strategy with which to retry a conflicting cherry-pick
+    pub strategy: Option<String>,
+    pub strategy_options: Vec<String>,
+    pub resume: Resume,
+    pub remotes: Remotes,
+    pub pull_request: PullRequest,
+    /// Write the prepared commits as a patch series in this directory, instead of pushing them
+    /// and opening a pull-request
+    pub format_patch: Option<PathBuf>,
+    /// Send the patch series once it is written, or only write the messages to `series.mbox` in
+    /// dry-run mode
+    pub send: bool,
+    pub mail: Mail,
+    /// GCC scripts with which to check the ChangeLogs of the prepared commits
+    pub changelogs: ChangeLogsOpt,
+    /// Refuse to push or send commits failing GCC's ChangeLog checks
+    pub strict: bool,
+}
+
+/// Commit which could not be cherry-picked
+#[derive(Debug)]
+pub struct Conflict {
+    pub commit: String,
+    pub files: Vec<String>,
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        if self.files.is_empty() {
+            write!(f, "{}", self.commit)
+        } else {
+            write!(
+                f,
+                "{} (conflicts in {})",
+                self.commit,
+                self.files.join(", ")
+            )
+        }
+    }
 }
 
 #[derive(Debug, Error)]
 pub enum Error {
+    #[error("I/O error: {0}")]
     Io(#[from] io::Error),
+    #[error("invalid UTF-8: {0}")]
     Utf8(#[from] string::FromUtf8Error),
+    #[error(transparent)]
     Git(#[from] git::Error),
-}
-
-impl Display for Error {
-    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-        write!(f, "{self:#?}")
-    }
+    #[error("GitHub error: {0}")]
+    Github(#[from] octocrab::Error),
+    #[error("could not cherry-pick {0}")]
+    Conflict(Conflict),
+    #[error("an upstream preparation is already in progress (`{}`): use `--continue` or `--abort`", .0.display())]
+    InProgress(PathBuf),
+    #[error("no upstream preparation in progress")]
+    NotInProgress,
+    #[error("{commit} was not cherry-picked on `{branch}`: cherry-pick it by hand before using `--continue`, or use `--abort`")]
+    NotPicked { commit: String, branch: String },
+    #[error("`{branch}` must only contain the cherry-pick of {commit} on top of where the run stopped, as a single commit which is not a merge")]
+    NotSinglePick { commit: String, branch: String },
+    #[error(
+        "a cherry-pick is still in progress: finish it with `git cherry-pick --continue` first"
+    )]
+    CherryPickInProgress,
+    #[error(transparent)]
+    Send(#[from] send::Error),
+    #[error(transparent)]
+    ChangeLog(#[from] clog::Error<'static>),
+    #[error("{count} prepared commits fail GCC's ChangeLog checks: amend them on `{branch}` and use `--continue`, or run without `--strict`")]
+    ChangeLogs { count: usize, branch: String },
+    #[error("no branch given on which to base the pull-request: use `--to` or set `to` in `gerris.toml`")]
+    MissingBase,
 }
 
 // shell script equivalent:
@@ -110,10 +285,9 @@ impl Display for Error {
 // git fetch upstream
 // # FIXME: The remotes need to exist already
 //
-// last_pushed_commit=$(git log -1 --grep "gccrs: " gcc/trunk --format="title")
-// last_msg = last_pushed_commit.strip_prefix("gccrs: ");
-// last_commit_us=$(git log -1 --grep $last_msg upstream/master --format="%h")
-// rev_list=$(git rev-list --no-merges $last_commit_us..upstream/master ^gcc/trunk -- gcc/rust/ libgrust/ gcc/testsuite/rust)
+// # commits already upstreamed are marked with `=`, the ones to prepare with `+`
+// marks=$(git rev-list --cherry-mark --right-only --no-merges --reverse gcc/trunk...upstream/master -- gcc/rust/ libgrust/ gcc/testsuite/rust)
+// rev_list=$(echo "$marks" | sed -n 's/^+//p')
 //
 // git checkout -b $date
 // for commit in rev_list.lines()
@@ -122,11 +296,73 @@ impl Display for Error {
 // git push -u origin HEAD
 // create_pr()
 
-pub fn maybe_prefix_cherry_picked_commit() -> Result<(), Error> {
+/// Spawn a command which modifies the repository, or only print it when running in dry-run mode
+async fn run<C: GitCmd>(cmd: C, repo: &git::Repo, dry_run: bool) -> Result<(), Error> {
+    if dry_run {
+        println!("{}", cmd.display(repo));
+    } else {
+        cmd.spawn_async(repo).await?;
+    }
+
+    Ok(())
+}
+
+/// Cherry-pick `commit`, retrying with the configured merge strategy in case of conflict. If the
+/// conflict remains, the cherry-pick is aborted so that the repository is left in a clean state.
+async fn cherry_pick(
+    repo: &git::Repo,
+    commit: &str,
+    strategy: Option<&str>,
+    strategy_options: &[String],
+    dry_run: bool,
+) -> Result<(), Error> {
+    let files = match run(git::cherry_pick(git::Commit(commit)), repo, dry_run).await {
+        Err(Error::Git(git::Error::CherryPickConflict { files, .. })) => files,
+        res => return res,
+    };
+
+    run(git::cherry_pick_abort(), repo, dry_run).await?;
+
+    let files = match strategy {
+        Some(strategy) => {
+            warn!("conflict when cherry-picking {commit}, retrying with strategy `{strategy}`...");
+
+            let cmd = strategy_options.iter().fold(
+                git::cherry_pick(git::Commit(commit)).strategy(strategy),
+                |cmd, option| cmd.strategy_option(option),
+            );
+
+            match run(cmd, repo, dry_run).await {
+                Err(Error::Git(git::Error::CherryPickConflict { files, .. })) => {
+                    run(git::cherry_pick_abort(), repo, dry_run).await?;
+                    files
+                }
+                res => return res,
+            }
+        }
+        None => files,
+    };
+
+    Err(Error::Conflict(Conflict {
+        commit: commit.to_string(),
+        files,
+    }))
+}
+
+/// Add the `gccrs: ` prefix to the commit which was just cherry-picked, if needed. The message is
+/// read from `rev`, which is the original commit after an automatic cherry-pick so that this also
+/// works in dry-run mode, where nothing was cherry-picked. Returns whether the commit was amended.
+pub async fn maybe_prefix_cherry_picked_commit(
+    repo: &git::Repo,
+    rev: &str,
+    dry_run: bool,
+) -> Result<bool, Error> {
     let msg = git::log()
         .amount(1)
         .format(git::Format::Body)
-        .spawn()?
+        .branch(git::Branch(rev))
+        .spawn_async(repo)
+        .await?
         .stdout;
 
     let commit = gccrs_tools::Commit::new(msg);
@@ -135,133 +371,1403 @@ pub fn maybe_prefix_cherry_picked_commit() -> Result<(), Error> {
         let new_msg = commit.maybe_prefix();
 
         info!("commit needs prefixing... adding `gccrs: ` prefix");
-        git::commit().amend().message(new_msg).spawn()?;
+        run(git::commit().amend().message(new_msg), repo, dry_run).await?;
+
+        return Ok(true);
     }
 
-    Ok(())
+    Ok(false)
 }
 
-fn prepare_body(last_commit: String, rev_list: String) -> String {
-    format!(
-        "
-This pull-request aims to help upstreaming commits to the GCC repository by formatting them \
-and checking that they can be cherry-picked/rebased properly.
+/// Gather what the pull-request lists about each prepared commit, warning about the ones which
+/// touch on common GCC directories and might need to be held back depending on the current GCC
+/// stage
+async fn describe_prepared(
+    repo: &git::Repo,
+    state: &State,
+    dirs: &[String],
+) -> Result<Vec<Prepared>, Error> {
+    let mut prepared = Vec::new();
 
-The last commit upstreamed was:
+    for entry in &state.commits {
+        let prefixed = match entry.state {
+            CommitState::Picked => false,
+            CommitState::Amended => true,
+            _ => continue,
+        };
 
-`{last_commit}`
-        
-The list of commits prepared is as follows:
-        
-{rev_list}
-        
-🐙
-        "
-    )
+        let log = |format| {
+            git::log()
+                .amount(1)
+                .format(format)
+                .branch(git::Branch(&entry.hash))
+                .spawn_async(repo)
+        };
+        let title = log(git::Format::Title).await?.stdout;
+        let author = log(git::Format::Author).await?.stdout;
+        let files = git::diff_tree(git::Commit(&entry.hash))
+            .files_async(repo)
+            .await?;
+
+        let outside: Vec<String> = files
+            .iter()
+            .filter(|file| {
+                !dirs
+                    .iter()
+                    .any(|dir| Path::new(&file.path).starts_with(dir))
+            })
+            .map(|file| format!("`{}`", file.path))
+            .collect();
+
+        let mut warnings = Vec::new();
+        if !outside.is_empty() {
+            warn!(
+                "{} touches on common GCC directories: {}",
+                entry.hash,
+                outside.join(", ")
+            );
+            warnings.push(format!(
+                "touches common GCC directories: {}",
+                outside.join(", ")
+            ));
+        }
+
+        prepared.push(Prepared {
+            hash: entry.hash.clone(),
+            picked: entry.picked.clone(),
+            title,
+            author,
+            files,
+            prefixed,
+            warnings,
+            changelog: None,
+        });
+    }
+
+    Ok(prepared)
 }
 
-pub async fn prepare_commits(
-    UpstreamOpt {
-        token,
-        branch,
-        gccrs,
-    }: UpstreamOpt,
+/// GCC commit on top of which the commits were prepared
+fn gcc_base(opt: &UpstreamOpt, state: &State) -> String {
+    match state.gcc_base.as_str() {
+        "" => opt.remotes.gcc_ref(),
+        base => base.to_string(),
+    }
+}
+
+/// Run a function of `clog`, which spawns processes synchronously, without blocking the runtime
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(value) => value,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+/// Run GCC's ChangeLog checker on the prepared branch, or gerris' own checks if the work
+/// directory does not contain it, and attach the errors along with a ChangeLog skeleton to each
+/// commit which fails. Unless running in strict mode, failing to run GCC's checker only skips the
+/// checks.
+async fn check_changelogs(
+    repo: &git::Repo,
+    opt: &UpstreamOpt,
+    state: &State,
+    prepared: &mut [Prepared],
 ) -> Result<(), Error> {
-    // let _ = CdRaii::change_path(gccrs);
-    std::env::set_current_dir(gccrs)?;
+    // nothing was cherry-picked in dry-run mode, so the original commits are checked instead
+    let ranges = if opt.dry_run {
+        prepared
+            .iter()
+            .map(|commit| format!("{0}^..{0}", commit.hash))
+            .collect()
+    } else {
+        vec![format!("{}..{}", gcc_base(opt, state), state.branch)]
+    };
 
-    info!("fetching `upstream`...");
-    git::fetch().remote("upstream").spawn()?;
+    let mut check_lines = Vec::new();
+    for range in ranges {
+        info!("checking ChangeLogs of `{range}`...");
 
-    info!("fetching `gcc`...");
-    git::fetch().remote("gcc").spawn()?;
+        let checks = {
+            let (repo, changelogs) = (repo.clone(), opt.changelogs.clone());
+            blocking(move || clog::check_range_or_natively(&repo, &changelogs, &range))
+        };
+        match checks.await {
+            Ok(lines) => check_lines.extend(lines),
+            Err(e) if !opt.strict => {
+                error!("could not check ChangeLogs: {e}");
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
 
-    let last_upstreamed_commit = git::log()
-        .amount(1)
-        .grep("gccrs: ")
-        .branch(git::Branch("gcc/trunk"))
-        .format(git::Format::Title)
-        .spawn()?
-        .stdout;
+    // the checks report the hashes of the prepared branch, or of the original commits in
+    // dry-run mode
+    let checked: Vec<String> = prepared
+        .iter()
+        .map(|commit| match opt.dry_run {
+            true => commit.hash.clone(),
+            false => commit.picked.clone(),
+        })
+        .collect();
+    let mut reported = vec![false; prepared.len()];
 
-    info!("found last upstreamed commit: {}", last_upstreamed_commit);
+    for check_line in check_lines {
+        let Some(index) = checked.iter().position(|hash| *hash == check_line.hash) else {
+            warn!(
+                "the ChangeLog checks reported on {}, which is not one of the prepared commits",
+                check_line.hash
+            );
+            continue;
+        };
+        reported[index] = true;
 
-    let last_msg = last_upstreamed_commit
-        .strip_prefix("gccrs: ")
-        .unwrap()
-        .trim_end();
+        if check_line.status != clog::Status::Failed {
+            continue;
+        }
 
-    let last_commit_us = git::log()
-        .amount(1)
-        .grep(last_msg)
-        .branch(git::Branch("upstream/master"))
-        .grep(last_msg)
-        .format(git::Format::Hash)
-        .spawn()?
-        .stdout;
+        let commit = &mut prepared[index];
+        let hash = check_line.hash;
+
+        warn!("{} fails GCC's ChangeLog checks", commit.hash);
+
+        let (repo, changelogs, rev) = (repo.clone(), opt.changelogs.clone(), hash.clone());
+        let skeleton = blocking(move || clog::skeleton(&repo, &changelogs, &rev))
+            .await
+            .map_err(|e| error!("could not generate ChangeLog skeleton for {hash}: {e}"))
+            .ok();
+
+        commit
+            .warnings
+            .push(String::from("fails GCC's ChangeLog checks"));
+        commit.changelog = Some(ChangeLogFailure {
+            diagnostics: check_line.diagnostics,
+            skeleton,
+        });
+    }
+
+    for (commit, reported) in prepared.iter().zip(reported) {
+        if !reported {
+            warn!("the ChangeLog checks did not report on {}", commit.hash);
+        }
+    }
+
+    Ok(())
+}
+
+/// Write the prepared commits as a numbered patch series replying to a cover letter, ready to be
+/// sent to GCC's mailing lists
+async fn format_patches(
+    repo: &git::Repo,
+    opt: &UpstreamOpt,
+    state: &State,
+    dir: &Path,
+    body: &Body<'_>,
+) -> Result<(), Error> {
+    let base = gcc_base(opt, state);
+
+    // git runs in the work directory, while `dir` is relative to ours
+    let dir = std::env::current_dir()?.join(dir);
+    let cmd = match (opt.dry_run, opt.send) {
+        // nothing is cherry-picked in dry-run mode, so the mbox is written from the original
+        // commits
+        (true, true) => {
+            git::format_patch_commits(body.prepared.iter().map(|commit| commit.hash.as_str()))
+        }
+        _ => git::format_patch(format!("{base}..{}", state.branch)),
+    }
+    .output_directory(&dir)
+    .cover_letter()
+    .numbered()
+    .thread();
+    let cmd = opt.mail.to().into_iter().fold(cmd, |cmd, to| cmd.to(to));
+    let cmd = opt.mail.cc.iter().fold(cmd, |cmd, cc| cmd.cc(cc));
 
-    info!("found equivalent commit: {}", last_commit_us);
+    let subject = format!("Rust front-end update {}", Local::now().date_naive());
+    let blurb = body.blurb();
 
-    let rev_list = git::rev_list(last_commit_us, "upstream/master")
+    if opt.dry_run {
+        println!("{}", cmd.clone().display(repo));
+        println!("\ncover letter: {subject}\n\n{blurb}");
+
+        if !opt.send {
+            return Ok(());
+        }
+    }
+
+    info!("writing patch series to `{}`...", dir.display());
+    let files = cmd.files_async(repo).await?;
+
+    if let Some(cover) = files
+        .iter()
+        .find(|file| file.ends_with("0000-cover-letter.patch"))
+    {
+        let letter = std::fs::read_to_string(cover)?
+            .replace("*** SUBJECT HERE ***", &subject)
+            .replace("*** BLURB HERE ***", &blurb);
+
+        std::fs::write(cover, letter)?;
+    }
+
+    match (opt.send, opt.dry_run) {
+        (true, false) => {
+            let smtp = opt.mail.smtp()?;
+
+            info!(
+                "sending {} messages through `{}`...",
+                files.len(),
+                smtp.server
+            );
+            send::send(&files, &smtp).await?;
+            warn!("sent the patch series to {}", opt.mail.to().join(", "));
+        }
+        (true, true) => {
+            let mbox = dir.join("series.mbox");
+
+            send::write_mbox(&files, opt.mail.from()?, &mbox)?;
+            warn!(
+                "wrote the messages which would be sent to `{}`",
+                mbox.display()
+            );
+        }
+        (false, _) => warn!(
+            "wrote {} files to `{}`: send them with `git send-email`",
+            files.len(),
+            dir.display()
+        ),
+    }
+
+    Ok(())
+}
+
+pub async fn prepare_commits(opt: UpstreamOpt) -> Result<(), Error> {
+    let repo = git::Repo::new(&opt.gccrs);
+
+    prepare_commits_in(&repo, opt).await
+}
+
+/// Save the state of the run, unless running in dry-run mode
+fn save(state: &State, repo: &git::Repo, dry_run: bool) -> Result<(), Error> {
+    if !dry_run {
+        state.save(repo.path())?;
+    }
+
+    Ok(())
+}
+
+async fn cherry_pick_in_progress(repo: &git::Repo) -> bool {
+    git::rev_parse("CHERRY_PICK_HEAD")
+        .verify()
+        .spawn_async(repo)
+        .await
+        .is_ok()
+}
+
+/// Fetch the remotes, figure out which commits need to be prepared and create the branch which
+/// will contain them
+async fn start(repo: &git::Repo, opt: &UpstreamOpt) -> Result<State, Error> {
+    if State::load(repo.path())?.is_some() {
+        return Err(Error::InProgress(State::path(repo.path())?));
+    }
+
+    // a patch series does not need a branch to open the pull-request against
+    let base = match (&opt.branch, &opt.format_patch) {
+        (Some(base), _) => base.clone(),
+        (None, Some(_)) => String::new(),
+        (None, None) => return Err(Error::MissingBase),
+    };
+
+    let remotes = &opt.remotes;
+
+    info!(
+        "fetching `{}` and `{}`...",
+        remotes.upstream_remote, remotes.gcc_remote
+    );
+    tokio::try_join!(
+        git::fetch()
+            .remote(&remotes.upstream_remote)
+            .spawn_async(repo),
+        git::fetch().remote(&remotes.gcc_remote).spawn_async(repo),
+    )?;
+
+    // commits are matched using their patch-id, as their hashes and titles differ between GCC's
+    // upstream and us
+    let marks = git::rev_list(remotes.gcc_ref(), remotes.upstream_ref())
+        .symmetric()
+        .right_only()
         .no_merges()
         .reverse()
-        .exclude(git::Branch("gcc/trunk"))
-        .dir("gcc/rust")
-        .dir("gcc/testsuite/rust")
-        .dir("libgrust")
-        .spawn()?
-        .stdout;
+        .dirs(opt.dirs.clone())
+        .marks_async(repo)
+        .await?;
+
+    let last_upstreamed_commit = match marks
+        .iter()
+        .rev()
+        .find(|(mark, _)| *mark == git::Mark::Equivalent)
+    {
+        Some((_, hash)) => {
+            let title = git::log()
+                .amount(1)
+                .branch(git::Branch(hash))
+                .format(git::Format::Title)
+                .spawn_async(repo)
+                .await?
+                .stdout;
+
+            format!("{} {title}", git::short(hash))
+        }
+        None => String::from("none"),
+    };
+
+    info!("found last upstreamed commit: {}", last_upstreamed_commit);
+
+    let commits: Vec<Entry> = marks
+        .into_iter()
+        .filter(|(mark, _)| *mark == git::Mark::Unique)
+        .map(|(_, hash)| Entry {
+            hash,
+            state: CommitState::Pending,
+            picked: String::new(),
+            files: Vec::new(),
+        })
+        .collect();
+
+    warn!("found {} commits to upstream", commits.len());
 
-    warn!("found {} commits to upstream", rev_list.lines().count());
+    let original_branch = git::rev_parse("HEAD")
+        .abbrev_ref()
+        .spawn_async(repo)
+        .await?
+        .stdout;
+    let gcc_base = git::rev_parse(remotes.gcc_ref())
+        .spawn_async(repo)
+        .await?
+        .stdout;
 
     let now = Local::now();
     let new_branch = format!("prepare-{}-{}", now.date_naive(), now.timestamp_micros());
-    git::branch()
-        .name(&new_branch)
-        .starting_point(git::StartingPoint::Branch("gcc/trunk"))
-        .spawn()?;
-    git::switch(&new_branch).spawn()?;
+    run(
+        git::branch()
+            .name(&new_branch)
+            .starting_point(git::StartingPoint::Branch(remotes.gcc_ref())),
+        repo,
+        opt.dry_run,
+    )
+    .await?;
+    run(git::switch(&new_branch), repo, opt.dry_run).await?;
 
     info!("created branch `{new_branch}`");
 
-    rev_list.lines().try_for_each(|commit| {
+    let state = State {
+        original_branch,
+        branch: new_branch,
+        base,
+        last_upstreamed_commit,
+        gcc_base,
+        head: String::new(),
+        commits,
+    };
+    save(&state, repo, opt.dry_run)?;
+
+    Ok(state)
+}
+
+/// Pick up a run which stopped on a conflict, once the conflicting commit has been cherry-picked
+/// by hand. The prepared branch must contain exactly one new commit, which is not a merge, on top
+/// of where the run stopped; its message is kept as the user left it apart from the `gccrs: `
+/// prefix.
+async fn resume(repo: &git::Repo, dry_run: bool) -> Result<State, Error> {
+    let mut state = State::load(repo.path())?.ok_or(Error::NotInProgress)?;
+
+    if cherry_pick_in_progress(repo).await {
+        return Err(Error::CherryPickInProgress);
+    }
+
+    run(git::switch(&state.branch), repo, dry_run).await?;
+
+    let conflicted = state
+        .with_state(CommitState::Conflicted)
+        .map(|entry| entry.hash.clone())
+        .next();
+
+    if let Some(commit) = conflicted {
+        // the branch must only contain the cherry-pick on top of where the run stopped
+        let picked = git::rev_list(&state.head, &state.branch)
+            .spawn_async(repo)
+            .await?
+            .stdout;
+        let merges = git::rev_list(&state.head, &state.branch)
+            .merges()
+            .spawn_async(repo)
+            .await?
+            .stdout;
+
+        let head = match picked.lines().collect::<Vec<_>>()[..] {
+            [] => {
+                return Err(Error::NotPicked {
+                    commit,
+                    branch: state.branch,
+                })
+            }
+            [head] if merges.is_empty() => head.to_string(),
+            _ => {
+                return Err(Error::NotSinglePick {
+                    commit,
+                    branch: state.branch,
+                })
+            }
+        };
+
+        if changed_paths(repo, &commit).await? != changed_paths(repo, &head).await? {
+            warn!(
+                "the cherry-pick of {commit} does not change the same files as the original: \
+                check that the conflict was resolved as intended"
+            );
+        }
+
+        info!("{commit} was cherry-picked by hand");
+
+        let amended = maybe_prefix_cherry_picked_commit(repo, &head, dry_run).await?;
+        state.head = String::new();
+        record_pick(repo, &mut state, &commit, amended, dry_run).await?;
+    }
+
+    Ok(state)
+}
+
+/// Paths of the files changed by `rev`, in order
+async fn changed_paths(repo: &git::Repo, rev: &str) -> Result<Vec<String>, Error> {
+    let mut paths: Vec<String> = git::diff_tree(git::Commit(rev))
+        .files_async(repo)
+        .await?
+        .into_iter()
+        .map(|file| file.path)
+        .collect();
+    paths.sort();
+
+    Ok(paths)
+}
+
+/// Restore the original branch and delete the partially prepared one
+async fn abort(repo: &git::Repo, dry_run: bool) -> Result<(), Error> {
+    let state = State::load(repo.path())?.ok_or(Error::NotInProgress)?;
+
+    if cherry_pick_in_progress(repo).await {
+        run(git::cherry_pick_abort(), repo, dry_run).await?;
+    }
+
+    run(git::switch(&state.original_branch), repo, dry_run).await?;
+    run(
+        git::branch().force_delete().name(&state.branch),
+        repo,
+        dry_run,
+    )
+    .await?;
+
+    if !dry_run {
+        State::remove(repo.path())?;
+    }
+
+    info!("aborted preparation of branch `{}`", state.branch);
+
+    Ok(())
+}
+
+/// Record that `commit` was prepared, along with the hash of its cherry-pick which is the last
+/// commit of the prepared branch
+async fn record_pick(
+    repo: &git::Repo,
+    state: &mut State,
+    commit: &str,
+    amended: bool,
+    dry_run: bool,
+) -> Result<(), Error> {
+    let new_state = if amended {
+        CommitState::Amended
+    } else {
+        CommitState::Picked
+    };
+    state.set(commit, new_state, Vec::new());
+
+    // nothing is cherry-picked in dry-run mode
+    if !dry_run {
+        let picked = git::rev_parse("HEAD").spawn_async(repo).await?.stdout;
+        state.set_picked(commit, picked);
+    }
+
+    save(state, repo, dry_run)
+}
+
+/// Cherry-pick and prefix all the commits which are still pending, recording the progress in the
+/// state file after each of them
+async fn pick_commits(repo: &git::Repo, state: &mut State, opt: &UpstreamOpt) -> Result<(), Error> {
+    let pending: Vec<String> = state
+        .with_state(CommitState::Pending)
+        .map(|entry| entry.hash.clone())
+        .collect();
+
+    for commit in pending {
         info!("cherry-picking {commit}...");
-        git::cherry_pick(git::Commit(commit)).spawn()?;
 
-        maybe_prefix_cherry_picked_commit()
-    })?;
+        match cherry_pick(
+            repo,
+            &commit,
+            opt.strategy.as_deref(),
+            &opt.strategy_options,
+            opt.dry_run,
+        )
+        .await
+        {
+            Ok(()) => {}
+            Err(Error::Conflict(conflict)) if opt.on_conflict == OnConflict::Skip => {
+                warn!("skipping {conflict}");
+                state.set(&commit, CommitState::Skipped, conflict.files);
+                save(state, repo, opt.dry_run)?;
+                continue;
+            }
+            Err(Error::Conflict(conflict)) => {
+                state.set(&commit, CommitState::Conflicted, conflict.files.clone());
+                state.head = git::rev_parse("HEAD").spawn_async(repo).await?.stdout;
+                save(state, repo, opt.dry_run)?;
+
+                error!(
+                    "could not cherry-pick {conflict}: stopping on branch `{}`. Cherry-pick the \
+                    commit by hand in `{}` and resume with `gerris upstream --continue`, or \
+                    use `gerris upstream --abort`",
+                    state.branch,
+                    repo.path().display()
+                );
+                return Err(Error::Conflict(conflict));
+            }
+            Err(e) => return Err(e),
+        }
+
+        let amended = maybe_prefix_cherry_picked_commit(repo, &commit, opt.dry_run).await?;
+        record_pick(repo, state, &commit, amended, opt.dry_run).await?;
+    }
+
+    Ok(())
+}
+
+async fn prepare_commits_in(repo: &git::Repo, opt: UpstreamOpt) -> Result<(), Error> {
+    // fail before preparing anything if the patch series cannot be sent
+    match (opt.resume, opt.send, opt.dry_run) {
+        (Resume::Abort, _, _) | (_, false, _) => {}
+        (_, true, false) => drop(opt.mail.smtp()?),
+        (_, true, true) => drop(opt.mail.from()?),
+    }
+
+    let mut state = match opt.resume {
+        Resume::Abort => return abort(repo, opt.dry_run).await,
+        Resume::Continue => resume(repo, opt.dry_run).await?,
+        Resume::Start => start(repo, &opt).await?,
+    };
+
+    pick_commits(repo, &mut state, &opt).await?;
+
+    let mut prepared = describe_prepared(repo, &state, &opt.dirs).await?;
+    check_changelogs(repo, &opt, &state, &mut prepared).await?;
+
+    let failing = prepared
+        .iter()
+        .filter(|commit| commit.changelog.is_some())
+        .count();
+    if opt.strict && failing > 0 {
+        return Err(Error::ChangeLogs {
+            count: failing,
+            branch: state.branch,
+        });
+    }
+
+    let skipped: Vec<Conflict> = state
+        .with_state(CommitState::Skipped)
+        .map(|entry| Conflict {
+            commit: entry.hash.clone(),
+            files: entry.files.clone(),
+        })
+        .collect();
+
+    let body = Body {
+        repo: &opt.remotes.repo,
+        last_upstreamed_commit: &state.last_upstreamed_commit,
+        gcc_base: &state.gcc_base,
+        prepared: &prepared,
+        skipped: &skipped,
+    };
+
+    if let Some(dir) = &opt.format_patch {
+        format_patches(repo, &opt, &state, dir, &body).await?;
+
+        if !opt.dry_run {
+            State::remove(repo.path())?;
+        }
+
+        return Ok(());
+    }
+
+    let instance = opt
+        .token
+        .map(|token| OctocrabBuilder::new().personal_token(token).build())
+        .transpose()?;
+    let existing = match &instance {
+        Some(instance) => {
+            github::find_open(
+                instance,
+                &opt.remotes.repo,
+                &state.base,
+                &opt.remotes.fork_owner,
+                &opt.pull_request.labels,
+            )
+            .await?
+        }
+        None => None,
+    };
 
     info!("pushing branch...");
-    git::push()
-        .upstream(git::Remote("origin"))
+    let push = git::push().upstream(git::Remote(&opt.remotes.push_remote));
+    let push = match &existing {
+        // update the branch of the open pull-request, unless someone pushed to it in the meantime
+        Some(existing) => push
+            .force_with_lease(
+                format!("refs/heads/{}", existing.head_ref),
+                &existing.head_sha,
+            )
+            .branch(git::Branch(format!(
+                "HEAD:refs/heads/{}",
+                existing.head_ref
+            ))),
         // TODO: Rename? This should be .refspec()?
-        .branch(git::Branch("HEAD"))
-        .spawn()?;
+        None => push.branch(git::Branch("HEAD")),
+    };
+    run(push, repo, opt.dry_run).await?;
 
-    if let Some(token) = token {
-        info!("creating pull-request...");
+    let title = github::title(Local::now().date_naive());
+    let head = format!("{}:{}", opt.remotes.fork_owner, state.branch);
+    let body = body.to_string();
 
-        let instance = OctocrabBuilder::new()
-            .personal_token(token)
-            .build()
-            .unwrap();
+    match (existing, instance) {
+        (Some(existing), Some(instance)) => {
+            let hashes: Vec<String> = prepared.iter().map(|c| c.hash.clone()).collect();
+            let comment = github::changes_comment(existing.previous.as_deref(), &hashes);
+
+            if opt.dry_run {
+                println!(
+                    "\npull-request #{} updated: {title}\n{body}\ncomment:\n{comment}",
+                    existing.number
+                );
+            } else {
+                github::update(
+                    &instance,
+                    &opt.remotes.repo,
+                    existing.number,
+                    title,
+                    body,
+                    comment,
+                )
+                .await?;
+            }
+        }
+        _ if opt.dry_run => println!(
+            "\npull-request `{head}` -> `{}`: {title}\n{}{body}",
+            state.base, opt.pull_request
+        ),
+        (_, Some(instance)) => {
+            info!("creating pull-request...");
+
+            let pr = instance
+                .pulls(&opt.remotes.repo.owner, &opt.remotes.repo.name)
+                .create(title, head, state.base)
+                .body(body)
+                .maintainer_can_modify(true)
+                .draft(opt.pull_request.draft)
+                .send()
+                .await?;
+
+            info!("created pull-request #{}", pr.number);
+
+            // the pull-request exists at this point, so failing to set it up must not prevent
+            // the run from completing
+            if let Err(e) =
+                github::setup(&instance, &opt.remotes.repo, pr.number, &opt.pull_request).await
+            {
+                error!("could not set up pull-request #{}: {e}", pr.number);
+            }
+        }
+        (_, None) => error!(
+            "no github token provided (`--token-file`, `GERRIS_TOKEN`, `GITHUB_TOKEN` or git's \
+            credential helper) - skipping pull-request creation!"
+        ),
+    }
+
+    if !opt.dry_run {
+        State::remove(repo.path())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Empty work directory, in which the state file can be written, removed once the test is
+    /// done
+    struct Work(PathBuf);
+
+    impl std::ops::Deref for Work {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for Work {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn work(name: &str) -> Work {
+        let work = std::env::temp_dir().join(format!("gerris-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&work);
+        std::fs::create_dir_all(work.join(".git")).unwrap();
+
+        Work(work)
+    }
+
+    fn opt(work: &Path) -> UpstreamOpt {
+        UpstreamOpt {
+            token: None,
+            branch: Some("gcc-patch-dev".to_string()),
+            gccrs: work.to_path_buf(),
+            dirs: GCCRS_DIRS.map(String::from).to_vec(),
+            dry_run: false,
+            on_conflict: OnConflict::Stop,
+            strategy: None,
+            strategy_options: Vec::new(),
+            resume: Resume::Start,
+            remotes: Remotes::default(),
+            pull_request: PullRequest::default(),
+            format_patch: None,
+            send: false,
+            mail: Mail::default(),
+            changelogs: ChangeLogsOpt::default(),
+            strict: false,
+        }
+    }
+
+    /// Commit prepared by a scripted run
+    #[derive(Clone, Copy)]
+    struct Commit {
+        hash: &'static str,
+        /// Hash of its cherry-pick on the prepared branch
+        picked: &'static str,
+        title: &'static str,
+        author: &'static str,
+        /// Output of `diff-tree` for the commit
+        files: &'static str,
+        /// Message of the cherry-pick, as read by the ChangeLog checks
+        message: &'static str,
+    }
+
+    const BAR: Commit = Commit {
+        hash: "e2c13b1",
+        picked: "b1a2r3",
+        title: "Add bar",
+        author: "Arthur Cohen",
+        files: ":000000 100644 0000000 257cc56 A\0gcc/rust/bar.cc\0\
+                1\t0\tgcc/rust/bar.cc\0",
+        message: "gccrs: Add bar\n\ngcc/rust/ChangeLog:\n\n\t* bar.cc: New file.",
+    };
+    const BAZ: Commit = Commit {
+        hash: "2f2cf24",
+        picked: "b4a5z6",
+        title: "gccrs: Fix baz",
+        author: "Pierre-Emmanuel Patry",
+        files: ":000000 100644 0000000 257cc56 A\0gcc/rust/baz.cc\0\
+                1\t0\tgcc/rust/baz.cc\0",
+        message: "gccrs: Fix baz\n\ngcc/rust/ChangeLog:\n\n\t* baz.cc: New file.",
+    };
+
+    /// Outputs of the git commands of a run, scripted step by step in the order gerris runs them
+    #[derive(Default)]
+    struct Script {
+        runner: git::Scripted,
+        dry_run: bool,
+    }
+
+    impl Script {
+        /// Fetch the remotes, find the `commits` to prepare and create the branch containing them
+        fn start(commits: &[Commit]) -> Script {
+            Script::find(commits, false).ok("").ok("") // branch, switch
+        }
 
-        instance
-            .pulls("rust-gcc", "gccrs")
-            .create(
-                format!("[upstream] [{}] Prepare commits", Local::now().date_naive()),
-                // FIXME: Will branches always be created and pushed from my fork? Add CLI parameter for this maybe?
-                format!("cohenarthur:{new_branch}"),
-                branch,
+        /// Find the `commits` to prepare in dry-run mode, where nothing is created
+        fn dry_run(commits: &[Commit]) -> Script {
+            Script::find(commits, true)
+        }
+
+        fn find(commits: &[Commit], dry_run: bool) -> Script {
+            let marks = commits
+                .iter()
+                .fold(String::from("=61b4d27"), |marks, commit| {
+                    format!("{marks}\n+{}", commit.hash)
+                });
+
+            Script {
+                runner: git::Scripted::default(),
+                dry_run,
+            }
+            .ok("") // fetch upstream
+            .ok("") // fetch gcc
+            .ok(&marks)
+            .ok("Add foo")
+            .ok("master")
+            .ok("a1b2c3d4e5f6") // gcc base
+        }
+
+        fn ok(self, stdout: &str) -> Script {
+            Script {
+                runner: self.runner.ok(stdout),
+                ..self
+            }
+        }
+
+        fn fail(self, code: i32, stdout: &str, stderr: &str) -> Script {
+            Script {
+                runner: self.runner.fail(code, stdout, stderr),
+                ..self
+            }
+        }
+
+        /// Cherry-pick `commit`, prefixing it if needed
+        fn pick(self, commit: &Commit) -> Script {
+            if self.dry_run {
+                return self.ok(commit.title);
+            }
+
+            let script = self.ok("").ok(commit.title);
+            let script = match commit.title.starts_with("gccrs: ") {
+                true => script,
+                false => script.ok(""), // commit --amend
+            };
+
+            script.ok(commit.picked) // HEAD
+        }
+
+        /// Fail to cherry-pick a commit, which is then aborted
+        fn conflict(self) -> Script {
+            self.fail(
+                1,
+                "CONFLICT (content): Merge conflict in gcc/rust/bar.cc",
+                "error: could not apply e2c13b1... Add bar",
             )
-            .body(prepare_body(last_upstreamed_commit, rev_list))
-            .maintainer_can_modify(true)
-            .send()
-            .await
+            .ok("") // cherry-pick --abort
+        }
+
+        /// Describe the prepared `commits` in the pull-request
+        fn describe(self, commits: &[Commit]) -> Script {
+            commits.iter().fold(self, |script, commit| {
+                script.ok(commit.title).ok(commit.author).ok(commit.files)
+            })
+        }
+
+        /// Run gerris' own ChangeLog checks on the prepared `commits`, or on the original ones in
+        /// dry-run mode
+        fn check(self, commits: &[Commit]) -> Script {
+            if self.dry_run {
+                return commits.iter().fold(self, |script, commit| {
+                    script.ok(commit.hash).ok(commit.message).ok(commit.files)
+                });
+            }
+
+            let hashes: Vec<&str> = commits.iter().map(|commit| commit.picked).collect();
+            commits
+                .iter()
+                .fold(self.ok(&hashes.join("\n")), |script, commit| {
+                    script.ok(commit.message).ok(commit.files)
+                })
+        }
+    }
+
+    fn starts_with(call: &[String], args: &[&str]) -> bool {
+        call.len() >= args.len() + 2
+            && call[2..]
+                .iter()
+                .zip(args)
+                .all(|(arg, expected)| arg == expected)
+    }
+
+    /// Whether a git command starting with `args` was run
+    fn ran(runner: &git::Scripted, args: &[&str]) -> bool {
+        runner.calls().iter().any(|call| starts_with(call, args))
+    }
+
+    /// Assert that git commands starting with each of `expected` were run in this order, possibly
+    /// among other commands
+    #[track_caller]
+    fn assert_ran(runner: &git::Scripted, expected: &[&[&str]]) {
+        let calls = runner.calls();
+        let mut remaining = calls.iter();
+
+        for args in expected {
+            assert!(
+                remaining.any(|call| starts_with(call, args)),
+                "`git {}` was not run in order in {calls:#?}",
+                args.join(" ")
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn prepare_commits_flow() {
+        let work = work("flow");
+        let runner = Script::start(&[BAR, BAZ])
+            .pick(&BAR)
+            .pick(&BAZ)
+            .describe(&[BAR, BAZ])
+            .check(&[BAR, BAZ])
+            .runner;
+        let repo = git::Repo::with_runner(&*work, runner.clone());
+
+        prepare_commits_in(&repo, opt(&work)).await.unwrap();
+
+        assert_ran(
+            &runner,
+            &[
+                &["fetch", "upstream"],
+                &["fetch", "gcc"],
+                &["rev-list", "gcc/trunk...upstream/master"],
+                &["log", "-1", "--format=%s", "61b4d27"],
+                &["rev-parse", "gcc/trunk"],
+                &["cherry-pick", "e2c13b1"],
+                &["commit", "--amend", "-m", "gccrs: Add bar"],
+                &["cherry-pick", "2f2cf24"],
+                &["log", "-1", "--format=%an", "e2c13b1"],
+                &["log", "-1", "--format=%B", "b1a2r3"],
+                &["log", "-1", "--format=%B", "b4a5z6"],
+                &["push", "-u", "origin", "HEAD"],
+            ],
+        );
+        // the second commit is already prefixed
+        assert!(!ran(
+            &runner,
+            &["commit", "--amend", "-m", "gccrs: Fix baz"]
+        ));
+        assert_eq!(State::load(&work).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn prepare_commits_dry_run() {
+        let work = work("dry-run");
+        let runner = Script::dry_run(&[BAZ])
+            .pick(&BAZ)
+            .describe(&[BAZ])
+            .check(&[BAZ])
+            .runner;
+        let repo = git::Repo::with_runner(&*work, runner.clone());
+
+        prepare_commits_in(
+            &repo,
+            UpstreamOpt {
+                dry_run: true,
+                ..opt(&work)
+            },
+        )
+        .await
+        .unwrap();
+
+        // the original commit is checked, as nothing was cherry-picked
+        assert_ran(
+            &runner,
+            &[
+                &["rev-list", "2f2cf24^..2f2cf24", "--reverse"],
+                &["log", "-1", "--format=%B", "2f2cf24"],
+            ],
+        );
+        for command in ["branch", "switch", "cherry-pick", "commit", "push"] {
+            assert!(!ran(&runner, &[command]), "`git {command}` was run");
+        }
+    }
+
+    #[tokio::test]
+    async fn prepare_commits_dry_run_writes_mbox() {
+        let work = work("mbox");
+        let dir = work.join("patches");
+        let cover = "From 32f22ae16764dcc82fcba7749f78d1bac0faaced Mon Sep 17 00:00:00 2001
+Message-Id: <cover.1699262222.git.arthur@gcc>
+From: Arthur Cohen <arthur@gcc>
+Subject: [PATCH 0/1] *** SUBJECT HERE ***
+
+*** BLURB HERE ***
+";
+        let patch = "From 2f2cf24 Mon Sep 17 00:00:00 2001
+Message-Id: <2f2cf24.1699262222.git.arthur@gcc>
+In-Reply-To: <cover.1699262222.git.arthur@gcc>
+References: <cover.1699262222.git.arthur@gcc>
+From: Pierre-Emmanuel Patry <pep@gcc>
+Subject: [PATCH 1/1] gccrs: Fix baz
+To: gcc-patches@gcc.gnu.org
+
+gcc/rust/ChangeLog:
+";
+        let files = [
+            dir.join("0000-cover-letter.patch"),
+            dir.join("0001-gccrs-Fix-baz.patch"),
+        ];
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&files[0], cover).unwrap();
+        std::fs::write(&files[1], patch).unwrap();
+
+        let listed: Vec<String> = files
+            .iter()
+            .map(|file| file.display().to_string())
+            .collect();
+        let runner = Script::dry_run(&[BAZ])
+            .pick(&BAZ)
+            .describe(&[BAZ])
+            .check(&[BAZ])
+            .ok(&listed.join("\n")) // format-patch
+            .runner;
+        let repo = git::Repo::with_runner(&*work, runner.clone());
+
+        prepare_commits_in(
+            &repo,
+            UpstreamOpt {
+                dry_run: true,
+                format_patch: Some(dir.clone()),
+                send: true,
+                mail: Mail {
+                    from: Some(String::from("Arthur Cohen <arthur@gcc>")),
+                    ..Mail::default()
+                },
+                ..opt(&work)
+            },
+        )
+        .await
+        .unwrap();
+
+        // the series is made of the original commits, as nothing was cherry-picked
+        let format_patch = runner
+            .calls()
+            .into_iter()
+            .find(|call| starts_with(call, &["format-patch"]))
             .unwrap();
-    } else {
-        error!("no github token provided - skipping pull-request creation!")
+        assert!(format_patch.ends_with(&["--no-walk=unsorted".to_string(), BAZ.hash.to_string()]));
+
+        let mbox = std::fs::read_to_string(dir.join("series.mbox")).unwrap();
+        assert!(mbox.contains("Subject: [PATCH 0/1] Rust front-end update "));
+        assert!(mbox.contains("\nMessage-Id: <2f2cf24.1699262222.git.arthur@gcc>\n"));
+        assert!(mbox.contains("\nIn-Reply-To: <cover.1699262222.git.arthur@gcc>\n"));
+        assert!(mbox.contains("\nReferences: <cover.1699262222.git.arthur@gcc>\n"));
+        assert!(mbox.contains("\n\nFrom: Pierre-Emmanuel Patry <pep@gcc>\n\ngcc/rust/ChangeLog"));
+        assert!(!ran(&runner, &["cherry-pick"]));
     }
 
-    Ok(())
+    #[tokio::test]
+    async fn prepare_commits_strict() {
+        let work = work("strict");
+        let bar = Commit {
+            title: "gccrs: Add bar",
+            ..BAR
+        };
+        let baz = Commit {
+            message: "gccrs: Fix baz",
+            ..BAZ
+        };
+        let runner = Script::start(&[bar, baz])
+            .pick(&bar)
+            .pick(&baz)
+            .describe(&[bar, baz])
+            .check(&[bar, baz])
+            .ok("diff --git a/gcc/rust/baz.cc b/gcc/rust/baz.cc\nnew file mode 100644\n+baz")
+            .runner;
+        let repo = git::Repo::with_runner(&*work, runner.clone());
+
+        let opt = UpstreamOpt {
+            strict: true,
+            ..opt(&work)
+        };
+
+        assert!(matches!(
+            prepare_commits_in(&repo, opt).await,
+            Err(Error::ChangeLogs { count: 1, .. })
+        ));
+
+        // the prepared branch is checked
+        assert!(runner
+            .calls()
+            .iter()
+            .any(|call| call[2] == "rev-list" && call[3].starts_with("a1b2c3d4e5f6..prepare-")));
+        // with a skeleton generated from the failing cherry-pick
+        assert!(ran(&runner, &["show", "-1", "b4a5z6"]));
+        assert!(!ran(&runner, &["push"]));
+        assert!(State::load(&work).unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn prepare_commits_unexpected_check() {
+        let work = work("unexpected");
+        let baz = Commit {
+            message: "gccrs: Fix baz",
+            ..BAZ
+        };
+        let runner = Script::start(&[BAR, baz])
+            .pick(&BAR)
+            .pick(&baz)
+            .describe(&[BAR, baz])
+            .ok("b1a2r3\nc0ffee")
+            .ok(BAR.message)
+            .ok(BAR.files)
+            .ok(baz.message)
+            .ok(baz.files)
+            .runner;
+        let repo = git::Repo::with_runner(&*work, runner.clone());
+
+        // the second commit of the branch is not the cherry-pick of any prepared commit, which
+        // is only reported, and leaves the other one unchecked
+        prepare_commits_in(&repo, opt(&work)).await.unwrap();
+
+        assert!(!ran(&runner, &["show", "-1", "c0ffee"]));
+        assert!(ran(&runner, &["push"]));
+    }
+
+    #[test]
+    fn github_repo() {
+        assert_eq!(
+            "rust-gcc/gccrs".parse(),
+            Ok(GithubRepo {
+                owner: "rust-gcc".into(),
+                name: "gccrs".into()
+            })
+        );
+        assert!("gccrs".parse::<GithubRepo>().is_err());
+        assert!("a/b/c".parse::<GithubRepo>().is_err());
+    }
+
+    #[tokio::test]
+    async fn prepare_commits_custom_remotes() {
+        let work = work("remotes");
+        let runner = Script::start(&[BAR, BAZ])
+            .pick(&BAR)
+            .pick(&BAZ)
+            .describe(&[BAR, BAZ])
+            .check(&[BAR, BAZ])
+            .runner;
+        let repo = git::Repo::with_runner(&*work, runner.clone());
+
+        prepare_commits_in(
+            &repo,
+            UpstreamOpt {
+                remotes: Remotes {
+                    upstream_remote: "gccrs".into(),
+                    gcc_remote: "sourceware".into(),
+                    gcc_branch: "master".into(),
+                    push_remote: "fork".into(),
+                    ..Remotes::default()
+                },
+                ..opt(&work)
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_ran(
+            &runner,
+            &[
+                &["fetch", "gccrs"],
+                &["fetch", "sourceware"],
+                &["rev-list", "sourceware/master...gccrs/master"],
+                &["rev-parse", "sourceware/master"],
+                &["push", "-u", "fork", "HEAD"],
+            ],
+        );
+    }
+
+    #[tokio::test]
+    async fn prepare_commits_stops_then_continues() {
+        let work = work("continue");
+        let runner = Script::start(&[BAR, BAZ])
+            .conflict()
+            .ok("b1a2r3") // HEAD
+            .runner;
+        let repo = git::Repo::with_runner(&*work, runner.clone());
+
+        let err = prepare_commits_in(&repo, opt(&work)).await.unwrap_err();
+
+        assert!(matches!(
+            err,
+            Error::Conflict(Conflict { commit, files })
+                if commit == "e2c13b1" && files == ["gcc/rust/bar.cc"]
+        ));
+        assert_ran(
+            &runner,
+            &[
+                &["cherry-pick", "e2c13b1"],
+                &["cherry-pick", "--abort"],
+                &["rev-parse", "HEAD"],
+            ],
+        );
+        assert!(!ran(&runner, &["cherry-pick", "2f2cf24"]));
+
+        let state = State::load(&work).unwrap().unwrap();
+        assert_eq!(state.commits[0].state, CommitState::Conflicted);
+        assert_eq!(state.commits[1].state, CommitState::Pending);
+        assert_eq!(state.head, "b1a2r3");
+
+        // starting a new run is refused while this one is in progress
+        let err = prepare_commits_in(&repo, opt(&work)).await.unwrap_err();
+        assert!(matches!(err, Error::InProgress(_)));
+
+        let continued = || UpstreamOpt {
+            branch: None,
+            resume: Resume::Continue,
+            ..opt(&work)
+        };
+        // continuing is refused until the commit is cherry-picked by hand, and when the branch
+        // contains anything else than that cherry-pick
+        let runner = git::Scripted::default()
+            .fail(1, "", "") // no cherry-pick in progress
+            .ok("") // switch
+            .ok("") // no new commit
+            .ok(""); // no merge
+        let repo = git::Repo::with_runner(&*work, runner);
+        let err = prepare_commits_in(&repo, continued()).await.unwrap_err();
+        assert!(matches!(err, Error::NotPicked { commit, .. } if commit == "e2c13b1"));
+
+        let runner = git::Scripted::default()
+            .fail(1, "", "") // no cherry-pick in progress
+            .ok("") // switch
+            .ok("h3e4a5\nf6a7b8")
+            .ok("");
+        let repo = git::Repo::with_runner(&*work, runner);
+        let err = prepare_commits_in(&repo, continued()).await.unwrap_err();
+        assert!(matches!(err, Error::NotSinglePick { commit, .. } if commit == "e2c13b1"));
+
+        let runner = git::Scripted::default()
+            .fail(1, "", "") // no cherry-pick in progress
+            .ok("") // switch
+            .ok("h3e4a5")
+            .ok("h3e4a5"); // a merge
+        let repo = git::Repo::with_runner(&*work, runner);
+        let err = prepare_commits_in(&repo, continued()).await.unwrap_err();
+        assert!(matches!(err, Error::NotSinglePick { commit, .. } if commit == "e2c13b1"));
+
+        let runner = Script::default()
+            .fail(1, "", "") // no cherry-pick in progress
+            .ok("") // switch
+            .ok("h3e4a5")
+            .ok("") // no merge
+            .ok(BAR.files)
+            .ok(BAR.files)
+            .ok("Add bar\n\nConflict resolved by hand.")
+            .ok("") // commit --amend
+            .ok(BAR.picked) // HEAD
+            .pick(&BAZ)
+            .describe(&[BAR, BAZ])
+            .check(&[BAR, BAZ])
+            .runner;
+        let repo = git::Repo::with_runner(&*work, runner.clone());
+
+        prepare_commits_in(&repo, continued()).await.unwrap();
+
+        let range = format!("b1a2r3..{}", state.branch);
+        assert_ran(
+            &runner,
+            &[
+                &["rev-parse", "--quiet", "--verify", "CHERRY_PICK_HEAD"],
+                &["switch", &state.branch],
+                &["rev-list", &range],
+                &["rev-list", &range, "--merges"],
+                &["log", "-1", "--format=%B", "h3e4a5"],
+                &[
+                    "commit",
+                    "--amend",
+                    "-m",
+                    "gccrs: Add bar\n\nConflict resolved by hand.",
+                ],
+                &["cherry-pick", "2f2cf24"],
+                &["push"],
+            ],
+        );
+        assert!(!ran(&runner, &["cherry-pick", "e2c13b1"]));
+        assert_eq!(State::load(&work).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn prepare_commits_aborts() {
+        let work = work("abort");
+        let runner = Script::start(&[BAR, BAZ]).conflict().runner;
+        let repo = git::Repo::with_runner(&*work, runner);
+
+        prepare_commits_in(&repo, opt(&work)).await.unwrap_err();
+
+        let branch = State::load(&work).unwrap().unwrap().branch;
+        let runner = git::Scripted::default().ok("CHERRY_PICK_HEAD");
+        let repo = git::Repo::with_runner(&*work, runner.clone());
+
+        prepare_commits_in(
+            &repo,
+            UpstreamOpt {
+                resume: Resume::Abort,
+                ..opt(&work)
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_ran(
+            &runner,
+            &[
+                &["cherry-pick", "--abort"],
+                &["switch", "master"],
+                &["branch", "-D", &branch],
+            ],
+        );
+        assert_eq!(State::load(&work).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn prepare_commits_retries_and_skips() {
+        let work = work("skip");
+        let runner = Script::start(&[BAR, BAZ])
+            .conflict()
+            .conflict()
+            .pick(&BAZ)
+            .describe(&[BAZ])
+            .check(&[BAZ])
+            .runner;
+        let repo = git::Repo::with_runner(&*work, runner.clone());
+
+        prepare_commits_in(
+            &repo,
+            UpstreamOpt {
+                on_conflict: OnConflict::Skip,
+                strategy: Some("ort".to_string()),
+                strategy_options: vec!["theirs".to_string()],
+                ..opt(&work)
+            },
+        )
+        .await
+        .unwrap();
+
+        assert_ran(
+            &runner,
+            &[
+                &["cherry-pick", "e2c13b1"],
+                &["cherry-pick", "--abort"],
+                &[
+                    "cherry-pick",
+                    "--strategy=ort",
+                    "--strategy-option=theirs",
+                    "e2c13b1",
+                ],
+                &["cherry-pick", "--abort"],
+                &["cherry-pick", "2f2cf24"],
+                &["push"],
+            ],
+        );
+    }
 }
@@ -0,0 +1,374 @@
+//! Body of the upstream pull-request, rendered as Markdown from the prepared commits
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::path::Path;
+
+use super::{github, Conflict, GithubRepo};
+use crate::clog::Diagnostic;
+use crate::git::{self, ChangedFile};
+
+/// Commit prepared for upstreaming, as listed in the pull-request
+#[derive(Debug)]
+pub struct Prepared {
+    /// Hash of the original commit in gccrs' repository
+    pub hash: String,
+    /// Hash of its cherry-pick on the prepared branch, empty when running in dry-run mode
+    pub picked: String,
+    pub title: String,
+    pub author: String,
+    pub files: Vec<ChangedFile>,
+    /// Whether gerris added the `gccrs: ` prefix
+    pub prefixed: bool,
+    pub warnings: Vec<String>,
+    /// Errors reported by GCC's ChangeLog checker, if the commit failed its checks
+    pub changelog: Option<ChangeLogFailure>,
+}
+
+#[derive(Debug)]
+pub struct ChangeLogFailure {
+    pub diagnostics: Vec<Diagnostic>,
+    /// ChangeLog skeleton of the commit, to help fixing the commit message
+    pub skeleton: Option<String>,
+}
+
+pub struct Body<'a> {
+    /// Repository the prepared commits come from, to link to them
+    pub repo: &'a GithubRepo,
+    pub last_upstreamed_commit: &'a str,
+    pub gcc_base: &'a str,
+    pub prepared: &'a [Prepared],
+    pub skipped: &'a [Conflict],
+}
+
+/// Escape the characters which would break a cell of a Markdown table
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+/// Deepest directory containing all the files
+fn common_dir(files: &[ChangedFile]) -> Option<&Path> {
+    let mut dirs = files
+        .iter()
+        .filter_map(|file| Path::new(&file.path).parent());
+    let first = dirs.next()?;
+
+    let common = dirs.fold(first, |common, dir| {
+        common
+            .ancestors()
+            .find(|ancestor| dir.starts_with(ancestor))
+            .unwrap_or(Path::new(""))
+    });
+
+    (!common.as_os_str().is_empty()).then_some(common)
+}
+
+/// Short description of the files changed by a commit, e.g. "3 files in `gcc/rust/ast` (+12 -4)"
+fn path_summary(files: &[ChangedFile]) -> String {
+    let added: usize = files.iter().filter_map(|file| file.added).sum();
+    let deleted: usize = files.iter().filter_map(|file| file.deleted).sum();
+    let lines = format!("(+{added} -{deleted})");
+
+    match (files, common_dir(files)) {
+        ([], _) => String::from("no changes"),
+        ([file], _) => format!("`{}` {lines}", file.path),
+        (files, Some(dir)) => format!("{} files in `{}` {lines}", files.len(), dir.display()),
+        (files, None) => format!("{} files {lines}", files.len()),
+    }
+}
+
+impl Prepared {
+    fn row(&self, repo: &GithubRepo) -> String {
+        let warnings = self
+            .warnings
+            .iter()
+            .map(|warning| format!("⚠️ {}", cell(warning)))
+            .collect::<Vec<_>>()
+            .join("<br>");
+
+        format!(
+            "| [`{}`](https://github.com/{repo}/commit/{}) | {} | {} | {} | {} | {warnings} |",
+            git::short(&self.hash),
+            self.hash,
+            cell(&self.title),
+            cell(&self.author),
+            path_summary(&self.files),
+            if self.prefixed { "✔️" } else { "" },
+        )
+    }
+
+    /// Errors of the ChangeLog checker and skeleton of the commit, as a Markdown section
+    fn changelog_failure(&self) -> String {
+        let Some(failure) = &self.changelog else {
+            return String::new();
+        };
+
+        let mut section = format!(
+            "#### `{}` {}\n\n```\n{}\n```\n\n",
+            git::short(&self.hash),
+            self.title,
+            failure
+                .diagnostics
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+        if let Some(skeleton) = &failure.skeleton {
+            section.push_str(&format!(
+                "<details><summary>ChangeLog skeleton</summary>\n\n```\n{}\n```\n\n</details>\n",
+                skeleton.trim_end()
+            ));
+        }
+
+        section
+    }
+}
+
+impl Body<'_> {
+    /// Number of commits prepared, prefixed, with warnings and skipped, each wrapped in `emphasis`
+    fn counts(&self, emphasis: &str) -> String {
+        let prefixed = self.prepared.iter().filter(|c| c.prefixed).count();
+        let warned = self
+            .prepared
+            .iter()
+            .filter(|c| !c.warnings.is_empty())
+            .count();
+        let count = |n: usize| format!("{emphasis}{n}{emphasis}");
+
+        format!(
+            "{} commits prepared, {} prefixed by gerris, {} with warnings, {} skipped.",
+            count(self.prepared.len()),
+            count(prefixed),
+            count(warned),
+            count(self.skipped.len())
+        )
+    }
+
+    /// Plain text version of the body, for the cover letter of a patch series
+    pub fn blurb(&self) -> String {
+        let mut blurb = format!(
+            "This series contains the following commits from gccrs, prepared on top of GCC \
+            commit {}. The last commit upstreamed was {}.\n\n",
+            self.gcc_base, self.last_upstreamed_commit
+        );
+
+        for commit in self.prepared {
+            let prefix = if commit.prefixed { "gccrs: " } else { "" };
+
+            blurb.push_str(&format!("- {prefix}{} ({})\n", commit.title, commit.author));
+            for warning in &commit.warnings {
+                blurb.push_str(&format!("  warning: {}\n", warning.replace('`', "")));
+            }
+        }
+
+        if !self.skipped.is_empty() {
+            blurb.push_str("\nThese commits could not be cherry-picked and were skipped:\n\n");
+            for conflict in self.skipped {
+                blurb.push_str(&format!("- {conflict}\n"));
+            }
+        }
+
+        blurb.push_str(&format!("\n{}", self.counts("")));
+
+        blurb
+    }
+}
+
+impl Display for Body<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        writeln!(
+            f,
+            "This pull-request aims to help upstreaming commits to the GCC repository by \
+            formatting them and checking that they can be cherry-picked/rebased properly.\n"
+        )?;
+        writeln!(
+            f,
+            "The last commit upstreamed was: `{}`\n",
+            self.last_upstreamed_commit
+        )?;
+
+        if self.prepared.is_empty() {
+            writeln!(f, "No commits were prepared.\n")?;
+        } else {
+            writeln!(
+                f,
+                "| Commit | Title | Author | Changes | `gccrs: ` added | Warnings |"
+            )?;
+            writeln!(f, "|---|---|---|---|:---:|---|")?;
+            for commit in self.prepared {
+                writeln!(f, "{}", commit.row(self.repo))?;
+            }
+            writeln!(f)?;
+        }
+
+        if !self.skipped.is_empty() {
+            writeln!(
+                f,
+                "❌ These commits could not be cherry-picked and were skipped:\n"
+            )?;
+            for conflict in self.skipped {
+                writeln!(f, "- {conflict}")?;
+            }
+            writeln!(f)?;
+        }
+
+        let failing: Vec<&Prepared> = self
+            .prepared
+            .iter()
+            .filter(|commit| commit.changelog.is_some())
+            .collect();
+        if !failing.is_empty() {
+            writeln!(f, "❌ These commits fail GCC's ChangeLog checks:\n")?;
+        }
+        for commit in failing {
+            writeln!(f, "{}", commit.changelog_failure())?;
+        }
+
+        writeln!(f, "{}\n", self.counts("**"))?;
+        writeln!(f, "Based on GCC commit `{}`.\n", self.gcc_base)?;
+        writeln!(f, "🐙")?;
+
+        let hashes: Vec<String> = self.prepared.iter().map(|c| c.hash.clone()).collect();
+        write!(f, "{}", github::marker(&hashes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::Status;
+
+    #[test]
+    fn summary() {
+        assert_eq!(
+            path_summary(&[ChangedFile::new(Status::Modified, "gcc/rust/bar.cc")]),
+            "`gcc/rust/bar.cc` (+1 -0)"
+        );
+        assert_eq!(
+            path_summary(&[
+                ChangedFile::new(Status::Modified, "gcc/rust/ast/rust-ast.h")
+                    .lines(Some(3), Some(1)),
+                ChangedFile::new(Status::Modified, "gcc/rust/ast/rust-ast.cc")
+                    .lines(Some(9), Some(3)),
+                ChangedFile::new(Status::Modified, "gcc/rust/ast/logo.png").lines(None, None),
+            ]),
+            "3 files in `gcc/rust/ast` (+12 -4)"
+        );
+        assert_eq!(
+            path_summary(&[
+                ChangedFile::new(Status::Modified, "gcc/rust/bar.cc").lines(Some(1), Some(1)),
+                ChangedFile::new(Status::Modified, "libgrust/foo.cc").lines(Some(1), Some(1)),
+            ]),
+            "2 files (+2 -2)"
+        );
+    }
+
+    #[test]
+    fn table() {
+        let repo = "rust-gcc/gccrs".parse().unwrap();
+        let prepared = [Prepared {
+            hash: "2f2cf24e3c1ab2f7b1c0a1d1c1f0e0d0c0b0a090".into(),
+            picked: String::new(),
+            title: "Fix `a | b` patterns".into(),
+            author: "Arthur Cohen".into(),
+            files: vec![
+                ChangedFile::new(Status::Modified, "gcc/other.c").lines(Some(2), Some(0)),
+                ChangedFile::new(Status::Modified, "gcc/rust/baz.cc").lines(Some(5), Some(1)),
+            ],
+            prefixed: true,
+            warnings: vec!["touches common GCC directories: `gcc/other.c`".into()],
+            changelog: None,
+        }];
+        let skipped = [Conflict {
+            commit: "e2c13b1".into(),
+            files: vec!["gcc/rust/bar.cc".into()],
+        }];
+
+        let body = Body {
+            repo: &repo,
+            last_upstreamed_commit: "61b4d27 Add foo",
+            gcc_base: "a1b2c3d4e5f6",
+            prepared: &prepared,
+            skipped: &skipped,
+        }
+        .to_string();
+
+        assert!(body.contains(
+            "| [`2f2cf24e3c1a`](https://github.com/rust-gcc/gccrs/commit/\
+            2f2cf24e3c1ab2f7b1c0a1d1c1f0e0d0c0b0a090) | Fix `a \\| b` patterns | Arthur Cohen \
+            | 2 files in `gcc` (+7 -1) | ✔️ | ⚠️ touches common GCC directories: `gcc/other.c` |"
+        ));
+        assert!(body.contains("- e2c13b1 (conflicts in gcc/rust/bar.cc)"));
+        assert!(body.contains(
+            "**1** commits prepared, **1** prefixed by gerris, **1** with warnings, **1** skipped."
+        ));
+        assert!(body.contains("Based on GCC commit `a1b2c3d4e5f6`."));
+    }
+
+    #[test]
+    fn changelog() {
+        let repo = "rust-gcc/gccrs".parse().unwrap();
+        let prepared = [Prepared {
+            hash: "2f2cf24e3c1a".into(),
+            picked: String::new(),
+            title: "gccrs: Fix baz".into(),
+            author: "Arthur Cohen".into(),
+            files: vec![
+                ChangedFile::new(Status::Modified, "gcc/rust/baz.cc").lines(Some(5), Some(1))
+            ],
+            prefixed: false,
+            warnings: vec![],
+            changelog: Some(ChangeLogFailure {
+                diagnostics: vec![Diagnostic::MissingLocation],
+                skeleton: Some("gcc/rust/ChangeLog:\n\n\t* baz.cc:\n".into()),
+            }),
+        }];
+
+        let body = Body {
+            repo: &repo,
+            last_upstreamed_commit: "61b4d27 Add foo",
+            gcc_base: "a1b2c3d4e5f6",
+            prepared: &prepared,
+            skipped: &[],
+        }
+        .to_string();
+
+        assert!(body.contains(
+            "❌ These commits fail GCC's ChangeLog checks:\n\n\
+            #### `2f2cf24e3c1a` gccrs: Fix baz\n\n\
+            ```\ncannot find a ChangeLog location in message\n```\n"
+        ));
+        assert!(body.contains("```\ngcc/rust/ChangeLog:\n\n\t* baz.cc:\n```"));
+    }
+
+    #[test]
+    fn blurb() {
+        let repo = "rust-gcc/gccrs".parse().unwrap();
+        let prepared = [Prepared {
+            hash: "2f2cf24".into(),
+            picked: String::new(),
+            title: "Fix baz".into(),
+            author: "Arthur Cohen".into(),
+            files: vec![ChangedFile::new(Status::Modified, "gcc/other.c").lines(Some(2), Some(0))],
+            prefixed: true,
+            warnings: vec!["touches common GCC directories: `gcc/other.c`".into()],
+            changelog: None,
+        }];
+
+        let blurb = Body {
+            repo: &repo,
+            last_upstreamed_commit: "61b4d27 Add foo",
+            gcc_base: "a1b2c3d4e5f6",
+            prepared: &prepared,
+            skipped: &[],
+        }
+        .blurb();
+
+        assert!(blurb.contains("on top of GCC commit a1b2c3d4e5f6"));
+        assert!(blurb.contains(
+            "- gccrs: Fix baz (Arthur Cohen)\n  warning: touches common GCC directories: gcc/other.c\n"
+        ));
+        assert!(blurb
+            .ends_with("1 commits prepared, 1 prefixed by gerris, 1 with warnings, 0 skipped."));
+    }
+}
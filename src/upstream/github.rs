@@ -0,0 +1,303 @@
+//! Creation and update of the upstream pull-request on GitHub
+
+use chrono::NaiveDate;
+use log::{error, info};
+use octocrab::{models::pulls, params, Octocrab};
+
+use super::{Error, GithubRepo};
+use crate::config::PullRequest;
+
+const TITLE_PREFIX: &str = "[upstream]";
+const TITLE_SUFFIX: &str = "Prepare commits";
+
+/// Hidden marker appended to the body of the pull-request, listing the commits it contains so
+/// that the next run can tell what changed
+const MARKER_START: &str = "<!-- gerris-prepared:";
+const MARKER_END: &str = "-->";
+
+pub fn title(date: NaiveDate) -> String {
+    format!("{TITLE_PREFIX} [{date}] {TITLE_SUFFIX}")
+}
+
+pub fn marker(prepared: &[String]) -> String {
+    format!("{MARKER_START} {} {MARKER_END}", prepared.join(" "))
+}
+
+fn parse_marker(body: &str) -> Option<Vec<String>> {
+    let (_, rest) = body.rsplit_once(MARKER_START)?;
+    let (hashes, _) = rest.split_once(MARKER_END)?;
+
+    Some(hashes.split_whitespace().map(String::from).collect())
+}
+
+/// Open pull-request created by a previous run, which can be updated instead of opening a new one
+#[derive(Debug)]
+pub struct Existing {
+    pub number: u64,
+    /// Branch of the fork from which the pull-request is opened
+    pub head_ref: String,
+    /// Commit the branch points to, used as the lease when force-pushing
+    pub head_sha: String,
+    /// Commits prepared in the pull-request, if it contains the marker
+    pub previous: Option<Vec<String>>,
+}
+
+/// Whether the pull-request was opened by gerris from `fork_owner`'s fork: it must carry one of
+/// the configured labels, or have a title following gerris' pattern
+fn is_upstream(
+    title: &str,
+    pr_labels: &[String],
+    head_label: &str,
+    fork_owner: &str,
+    labels: &[String],
+) -> bool {
+    let from_fork = head_label
+        .split_once(':')
+        .is_some_and(|(owner, _)| owner.eq_ignore_ascii_case(fork_owner));
+    let labelled = pr_labels.iter().any(|label| labels.contains(label));
+    let titled = title.starts_with(TITLE_PREFIX) && title.ends_with(TITLE_SUFFIX);
+
+    from_fork && (labelled || titled)
+}
+
+fn to_existing(pr: pulls::PullRequest) -> Existing {
+    Existing {
+        number: pr.number,
+        head_ref: pr.head.ref_field,
+        head_sha: pr.head.sha,
+        previous: pr.body.as_deref().and_then(parse_marker),
+    }
+}
+
+/// Look for the most recent open upstream pull-request targeting `base`
+pub async fn find_open(
+    instance: &Octocrab,
+    repo: &GithubRepo,
+    base: &str,
+    fork_owner: &str,
+    labels: &[String],
+) -> Result<Option<Existing>, Error> {
+    info!("looking for an open upstream pull-request...");
+
+    let page = instance
+        .pulls(&repo.owner, &repo.name)
+        .list()
+        .state(params::State::Open)
+        .base(base)
+        .sort(params::pulls::Sort::Created)
+        .direction(params::Direction::Descending)
+        .per_page(100)
+        .send()
+        .await?;
+
+    let existing = instance
+        .all_pages(page)
+        .await?
+        .into_iter()
+        .find(|pr| {
+            let pr_labels: Vec<String> = pr
+                .labels
+                .iter()
+                .flatten()
+                .map(|label| label.name.clone())
+                .collect();
+
+            is_upstream(
+                pr.title.as_deref().unwrap_or_default(),
+                &pr_labels,
+                pr.head.label.as_deref().unwrap_or_default(),
+                fork_owner,
+                labels,
+            )
+        })
+        .map(to_existing);
+
+    if let Some(existing) = &existing {
+        info!("found open pull-request #{}", existing.number);
+    }
+
+    Ok(existing)
+}
+
+fn list(title: &str, commits: &[&String]) -> String {
+    commits.iter().fold(format!("\n{title}\n"), |msg, commit| {
+        format!("{msg}\n- {commit}")
+    })
+}
+
+/// Summary of the changes between the commits previously prepared in the pull-request and the
+/// new ones
+pub fn changes_comment(previous: Option<&[String]>, prepared: &[String]) -> String {
+    let header = "🔄 The prepared commits were updated.\n";
+
+    let Some(previous) = previous else {
+        return list(
+            &format!(
+                "{header}\nThe previous list of commits is unknown. The pull-request now contains:"
+            ),
+            &prepared.iter().collect::<Vec<_>>(),
+        );
+    };
+
+    let added: Vec<&String> = prepared
+        .iter()
+        .filter(|commit| !previous.contains(commit))
+        .collect();
+    let removed: Vec<&String> = previous
+        .iter()
+        .filter(|commit| !prepared.contains(commit))
+        .collect();
+
+    if added.is_empty() && removed.is_empty() {
+        return format!(
+            "{header}\nThe list of commits is unchanged, they were prepared again on top of \
+            GCC's latest changes."
+        );
+    }
+
+    let mut comment = String::from(header);
+    if !added.is_empty() {
+        comment.push_str(&list("New commits:", &added));
+        comment.push('\n');
+    }
+    if !removed.is_empty() {
+        comment.push_str(&list("Commits no longer prepared:", &removed));
+        comment.push('\n');
+    }
+
+    comment
+}
+
+/// Request reviews, add labels and set the milestone of a freshly created pull-request
+pub async fn setup(
+    instance: &Octocrab,
+    repo: &GithubRepo,
+    number: u64,
+    pr: &PullRequest,
+) -> Result<(), Error> {
+    if !pr.reviewers.is_empty() || !pr.team_reviewers.is_empty() {
+        info!("requesting reviews...");
+        instance
+            .pulls(&repo.owner, &repo.name)
+            .request_reviews(number, pr.reviewers.clone(), pr.team_reviewers.clone())
+            .await?;
+    }
+
+    let issues = instance.issues(&repo.owner, &repo.name);
+
+    if !pr.labels.is_empty() {
+        info!("adding labels...");
+        issues.add_labels(number, &pr.labels).await?;
+    }
+
+    if let Some(milestone) = pr.milestone {
+        info!("setting milestone...");
+        issues.update(number).milestone(milestone).send().await?;
+    }
+
+    Ok(())
+}
+
+/// Rewrite the title and body of an existing pull-request, and comment with the changes since
+/// the previous push
+pub async fn update(
+    instance: &Octocrab,
+    repo: &GithubRepo,
+    number: u64,
+    title: String,
+    body: String,
+    comment: String,
+) -> Result<(), Error> {
+    info!("updating pull-request #{number}...");
+
+    instance
+        .pulls(&repo.owner, &repo.name)
+        .update(number)
+        .title(title)
+        .body(body)
+        .send()
+        .await?;
+
+    // the branch and body are up to date at this point, so a missing comment is not fatal
+    if let Err(e) = instance
+        .issues(&repo.owner, &repo.name)
+        .create_comment(number, comment)
+        .await
+    {
+        error!("could not comment on pull-request #{number}: {e}");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(strings: &[&str]) -> Vec<String> {
+        strings.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn upstream_pull_request() {
+        let labels = strings(&["upstream"]);
+        let title = title(NaiveDate::from_ymd_opt(2023, 11, 6).unwrap());
+
+        assert!(is_upstream(
+            &title,
+            &[],
+            "cohenarthur:prepare",
+            "CohenArthur",
+            &[]
+        ));
+        assert!(is_upstream(
+            "Upstream",
+            &labels,
+            "cohenarthur:prepare",
+            "cohenarthur",
+            &labels
+        ));
+        assert!(!is_upstream(
+            &title,
+            &[],
+            "p-e-p:prepare",
+            "cohenarthur",
+            &labels
+        ));
+        assert!(!is_upstream(
+            "Add foo",
+            &[],
+            "cohenarthur:foo",
+            "cohenarthur",
+            &labels
+        ));
+    }
+
+    #[test]
+    fn marker_roundtrip() {
+        let prepared = strings(&["e2c13b1", "2f2cf24"]);
+        let body = format!(
+            "The list of commits prepared is as follows:\n\n{}",
+            marker(&prepared)
+        );
+
+        assert_eq!(parse_marker(&body), Some(prepared));
+        assert_eq!(parse_marker("no marker here"), None);
+    }
+
+    #[test]
+    fn changes() {
+        let previous = strings(&["e2c13b1", "2f2cf24"]);
+
+        let comment = changes_comment(Some(&previous), &strings(&["2f2cf24", "8b0d2a1"]));
+        assert!(comment.contains("New commits:\n\n- 8b0d2a1"));
+        assert!(comment.contains("Commits no longer prepared:\n\n- e2c13b1"));
+
+        let comment = changes_comment(Some(&previous), &previous);
+        assert!(comment.contains("unchanged"));
+
+        let comment = changes_comment(None, &previous);
+        assert!(comment.contains("unknown"));
+        assert!(comment.contains("- 2f2cf24"));
+    }
+}
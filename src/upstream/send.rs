@@ -0,0 +1,502 @@
+//! Sending of the patch series to GCC's mailing lists over SMTP, the way `git send-email` would:
+//! messages are sent in order, as the configured sender, with the original author kept in the
+//! body and the threading headers written by `git format-patch`
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use lettre::address::{AddressError, Envelope};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::smtp::client::{Tls, TlsParameters};
+use lettre::{Address, AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use log::info;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::config::Mail;
+
+/// How to secure the connection to the SMTP server
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Encryption {
+    /// Upgrade the connection with `STARTTLS`, on port 587 by default
+    #[default]
+    Starttls,
+    /// Connect over TLS directly, on port 465 by default
+    Tls,
+    /// Send everything in clear text, on port 25 by default. Only meant for local servers
+    None,
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("SMTP error: {0}")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+    #[error("invalid address `{0}`: {1}")]
+    Address(String, AddressError),
+    #[error("invalid envelope: {0}")]
+    Envelope(#[from] lettre::error::Error),
+    #[error("no recipients in `{}`", .0.display())]
+    NoRecipients(PathBuf),
+    #[error(
+        "no sender given for the patch series: use `--mail-from` or set `from` in `gerris.toml`"
+    )]
+    MissingSender,
+    #[error("no SMTP server given: use `--smtp-server` or set `smtp-server` in `gerris.toml`")]
+    MissingSmtpServer,
+}
+
+pub struct Smtp {
+    pub server: String,
+    pub port: Option<u16>,
+    pub encryption: Encryption,
+    pub credentials: Option<(String, String)>,
+    /// Sender of the messages, e.g. `Arthur Cohen <arthur.cohen@embecosm.com>`
+    pub from: String,
+    /// Time to wait between two messages, so that the mailing list is not flooded
+    pub delay: Duration,
+}
+
+/// Default recipient of the patch series
+const GCC_PATCHES: &str = "gcc-patches@gcc.gnu.org";
+
+impl Mail {
+    pub(super) fn to(&self) -> Vec<String> {
+        if self.to.is_empty() {
+            vec![String::from(GCC_PATCHES)]
+        } else {
+            self.to.clone()
+        }
+    }
+
+    pub(super) fn from(&self) -> Result<&str, Error> {
+        self.from.as_deref().ok_or(Error::MissingSender)
+    }
+
+    pub(super) fn smtp(&self) -> Result<Smtp, Error> {
+        let password = match &self.smtp_password_file {
+            Some(path) => Some(fs::read_to_string(path)?.trim().to_string()),
+            None => std::env::var("GERRIS_SMTP_PASSWORD").ok(),
+        };
+
+        Ok(Smtp {
+            server: self.smtp_server.clone().ok_or(Error::MissingSmtpServer)?,
+            port: self.smtp_port,
+            encryption: self.smtp_encryption.unwrap_or_default(),
+            credentials: self.smtp_user.clone().zip(password),
+            from: self.from()?.to_string(),
+            delay: Duration::from_secs(self.delay.unwrap_or(1)),
+        })
+    }
+}
+
+/// Message written by `git format-patch`
+#[derive(Debug)]
+struct Message {
+    /// `From <hash> Mon Sep 17 00:00:00 2001` line separating messages in an mbox
+    separator: String,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Message {
+    fn parse(patch: &str) -> Message {
+        let (separator, patch) = match patch.split_once('\n') {
+            Some((first, rest)) if first.starts_with("From ") => (first.to_string(), rest),
+            _ => (String::from("From gerris Mon Sep 17 00:00:00 2001"), patch),
+        };
+        let (head, body) = patch.split_once("\n\n").unwrap_or((patch, ""));
+
+        // folded headers continue on lines starting with whitespace
+        let headers = head.lines().fold(Vec::new(), |mut headers, line| {
+            match (line.starts_with([' ', '\t']), headers.last_mut()) {
+                (true, Some((_, value))) => *value = format!("{value} {}", line.trim()),
+                _ => {
+                    if let Some((name, value)) = line.split_once(':') {
+                        headers.push((name.to_string(), value.trim().to_string()));
+                    }
+                }
+            }
+            headers
+        });
+
+        Message {
+            separator,
+            headers,
+            body: body.to_string(),
+        }
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn set_header(&mut self, name: &str, value: String) {
+        match self
+            .headers
+            .iter_mut()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        {
+            Some((_, old)) => *old = value,
+            None => self.headers.push((name.to_string(), value)),
+        }
+    }
+
+    /// Send the message as `sender`, keeping the author in the first line of the body so that
+    /// `git am` still attributes the patch to them
+    fn resend(&mut self, sender: &str, date: DateTime<Local>) {
+        if let Some(author) = self.header("From").map(String::from) {
+            if author != sender {
+                self.body = format!("From: {author}\n\n{}", self.body);
+            }
+        }
+
+        self.set_header("From", sender.to_string());
+        self.set_header("Date", date.to_rfc2822());
+    }
+
+    fn recipients(&self) -> Vec<String> {
+        ["To", "Cc"]
+            .iter()
+            .filter_map(|name| self.header(name))
+            .flat_map(addresses)
+            .collect()
+    }
+
+    fn render(&self) -> String {
+        let headers = self
+            .headers
+            .iter()
+            .map(|(name, value)| format!("{name}: {value}\n"))
+            .collect::<String>();
+
+        format!("{headers}\n{}", self.body)
+    }
+}
+
+/// Split a list of recipients, ignoring the commas inside of quoted names, and keep the address
+/// of each of them
+fn addresses(list: &str) -> Vec<String> {
+    let mut recipients = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+
+    for c in list.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => recipients.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    recipients.push(current);
+
+    recipients
+        .iter()
+        .map(|recipient| match recipient.split_once('<') {
+            Some((_, address)) => address.trim_end().trim_end_matches('>').trim(),
+            None => recipient.trim(),
+        })
+        .filter(|address| !address.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn address(recipient: &str) -> Result<Address, Error> {
+    let address = addresses(recipient)
+        .pop()
+        .unwrap_or_else(|| recipient.to_string());
+
+    address
+        .parse()
+        .map_err(|e| Error::Address(address.clone(), e))
+}
+
+/// Read the patches and prepare them to be sent by `sender`. Dates are spaced by a second so that
+/// the series is displayed in order.
+fn messages(files: &[PathBuf], sender: &str) -> Result<Vec<(PathBuf, Message)>, Error> {
+    let now = Local::now();
+
+    files
+        .iter()
+        .zip(0..)
+        .map(|(file, i)| {
+            let mut message = Message::parse(&fs::read_to_string(file)?);
+            message.resend(sender, now + chrono::Duration::seconds(i));
+
+            Ok((file.clone(), message))
+        })
+        .collect()
+}
+
+/// Write the messages which would be sent to an mbox, escaping the lines of the bodies which
+/// could be mistaken for message separators
+pub fn write_mbox(files: &[PathBuf], sender: &str, mbox: &Path) -> Result<(), Error> {
+    let content = messages(files, sender)?
+        .iter()
+        .map(|(_, message)| {
+            let escaped = message
+                .render()
+                .lines()
+                .map(
+                    |line| match line.trim_start_matches('>').starts_with("From ") {
+                        true => format!(">{line}\n"),
+                        false => format!("{line}\n"),
+                    },
+                )
+                .collect::<String>();
+
+            format!("{}\n{escaped}\n", message.separator)
+        })
+        .collect::<String>();
+
+    fs::write(mbox, content)?;
+
+    Ok(())
+}
+
+fn transport(smtp: &Smtp) -> Result<AsyncSmtpTransport<Tokio1Executor>, Error> {
+    let builder = match smtp.encryption {
+        Encryption::Starttls => {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&smtp.server)
+                .port(587)
+                .tls(Tls::Required(TlsParameters::new(smtp.server.clone())?))
+        }
+        Encryption::Tls => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&smtp.server)
+            .port(465)
+            .tls(Tls::Wrapper(TlsParameters::new(smtp.server.clone())?)),
+        Encryption::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&smtp.server),
+    };
+
+    let builder = match smtp.port {
+        Some(port) => builder.port(port),
+        None => builder,
+    };
+    let builder = match &smtp.credentials {
+        Some((user, password)) => {
+            builder.credentials(Credentials::new(user.clone(), password.clone()))
+        }
+        None => builder,
+    };
+
+    Ok(builder.build())
+}
+
+/// Send the patches in order, waiting for the configured delay between two messages
+pub async fn send(files: &[PathBuf], smtp: &Smtp) -> Result<(), Error> {
+    let transport = transport(smtp)?;
+    let sender = address(&smtp.from)?;
+    let messages = messages(files, &smtp.from)?;
+
+    for (i, (file, message)) in messages.iter().enumerate() {
+        if i != 0 {
+            tokio::time::sleep(smtp.delay).await;
+        }
+
+        let recipients = message
+            .recipients()
+            .iter()
+            .map(|recipient| address(recipient))
+            .collect::<Result<Vec<_>, _>>()?;
+        if recipients.is_empty() {
+            return Err(Error::NoRecipients(file.clone()));
+        }
+
+        let envelope = Envelope::new(Some(sender.clone()), recipients)?;
+        // SMTP expects CRLF line endings, which some patches already use
+        let content = message
+            .render()
+            .lines()
+            .map(|line| format!("{line}\r\n"))
+            .collect::<String>();
+
+        info!(
+            "sending `{}`...",
+            message.header("Subject").unwrap_or_default()
+        );
+        transport.send_raw(&envelope, content.as_bytes()).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    const COVER: &str = "From 32f22ae16764dcc82fcba7749f78d1bac0faaced Mon Sep 17 00:00:00 2001
+Message-Id: <cover.1699262222.git.arthur@gcc>
+From: Arthur Cohen <arthur@gcc>
+Date: Mon, 6 Nov 2023 10:17:02 +0100
+Subject: [PATCH 0/1] Rust front-end update 2023-11-06
+To: gcc-patches@gcc.gnu.org
+Cc: \"Rust, GCC\" <gcc-rust@gcc.gnu.org>
+
+This series contains the following commits from gccrs.
+";
+
+    const PATCH: &str = "From 61a92b3edeb8bab3cd1a43e3c74bed18dbb3ae27 Mon Sep 17 00:00:00 2001
+Message-Id: <61a92b3edeb8.1699262222.git.arthur@gcc>
+In-Reply-To: <cover.1699262222.git.arthur@gcc>
+References: <cover.1699262222.git.arthur@gcc>
+From: Pierre-Emmanuel Patry <pep@gcc>
+Date: Thu, 2 Nov 2023 10:17:02 +0100
+Subject: [PATCH 1/1] gccrs: Add bar
+To: gcc-patches@gcc.gnu.org
+Cc: \"Rust, GCC\" <gcc-rust@gcc.gnu.org>
+
+gcc/rust/ChangeLog:
+
+\t* bar.cc: New file.
+---
+.leading dot
+From the start of a line
++  return 0;\r
+";
+
+    fn series(name: &str) -> Vec<PathBuf> {
+        let dir = std::env::temp_dir().join(format!("gerris-{name}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        [
+            ("0000-cover-letter.patch", COVER),
+            ("0001-gccrs-Add-bar.patch", PATCH),
+        ]
+        .iter()
+        .map(|(name, content)| {
+            let file = dir.join(name);
+            fs::write(&file, content).unwrap();
+            file
+        })
+        .collect()
+    }
+
+    #[test]
+    fn recipients() {
+        let message = Message::parse(PATCH);
+
+        assert_eq!(
+            message.header("subject"),
+            Some("[PATCH 1/1] gccrs: Add bar")
+        );
+        assert_eq!(
+            message.recipients(),
+            ["gcc-patches@gcc.gnu.org", "gcc-rust@gcc.gnu.org"]
+        );
+    }
+
+    #[test]
+    fn mbox() {
+        let files = series("mbox");
+        let mbox = files[0].with_file_name("series.mbox");
+
+        write_mbox(&files, "Arthur Cohen <arthur@gcc>", &mbox).unwrap();
+        let content = fs::read_to_string(&mbox).unwrap();
+
+        assert!(content.starts_with("From 32f22ae16764dcc82fcba7749f78d1bac0faaced Mon Sep"));
+        assert!(content.contains("\nFrom 61a92b3edeb8bab3cd1a43e3c74bed18dbb3ae27 Mon Sep"));
+        assert!(content.contains("From: Arthur Cohen <arthur@gcc>\nDate: "));
+        assert!(content.contains("\n\nFrom: Pierre-Emmanuel Patry <pep@gcc>\n\ngcc/rust/ChangeLog"));
+        assert!(content.contains("\n>From the start of a line\n"));
+
+        fs::remove_dir_all(mbox.parent().unwrap()).unwrap();
+    }
+
+    /// Minimal SMTP server accepting every message, which answers `connections` connections and
+    /// returns the recipients and content of the messages it received
+    async fn stand_in(listener: TcpListener, connections: usize) -> Vec<(Vec<String>, String)> {
+        let mut received = Vec::new();
+
+        for _ in 0..connections {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read, mut write) = stream.into_split();
+            let mut lines = BufReader::new(read).lines();
+            let mut recipients = Vec::new();
+
+            write.write_all(b"220 stand-in ESMTP\r\n").await.unwrap();
+
+            while let Some(line) = lines.next_line().await.unwrap() {
+                let reply: &[u8] = match line.split_whitespace().next().unwrap_or_default() {
+                    "EHLO" => b"250 stand-in\r\n",
+                    "RCPT" => {
+                        recipients.push(line.trim_start_matches("RCPT TO:").to_string());
+                        b"250 OK\r\n"
+                    }
+                    "DATA" => {
+                        write.write_all(b"354 go ahead\r\n").await.unwrap();
+
+                        let mut data = String::new();
+                        while let Some(line) = lines.next_line().await.unwrap() {
+                            if line == "." {
+                                break;
+                            }
+                            data.push_str(&line);
+                            data.push('\n');
+                        }
+                        received.push((std::mem::take(&mut recipients), data));
+
+                        b"250 queued\r\n"
+                    }
+                    "QUIT" => {
+                        write.write_all(b"221 bye\r\n").await.unwrap();
+                        break;
+                    }
+                    _ => b"250 OK\r\n",
+                };
+
+                write.write_all(reply).await.unwrap();
+            }
+        }
+
+        received
+    }
+
+    #[tokio::test]
+    async fn send_to_stand_in() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(stand_in(listener, 2));
+
+        let files = series("smtp");
+        let smtp = Smtp {
+            server: String::from("127.0.0.1"),
+            port: Some(port),
+            encryption: Encryption::None,
+            credentials: None,
+            from: String::from("Arthur Cohen <arthur@gcc>"),
+            delay: Duration::ZERO,
+        };
+
+        send(&files, &smtp).await.unwrap();
+        let received = server.await.unwrap();
+
+        assert_eq!(received.len(), 2);
+        assert_eq!(
+            received[1].0,
+            ["<gcc-patches@gcc.gnu.org>", "<gcc-rust@gcc.gnu.org>"]
+        );
+
+        let patch = &received[1].1;
+        assert!(patch.contains("In-Reply-To: <cover.1699262222.git.arthur@gcc>\n"));
+        assert!(patch.contains("From: Arthur Cohen <arthur@gcc>\n"));
+        assert!(patch.contains("\n\nFrom: Pierre-Emmanuel Patry <pep@gcc>\n\ngcc/rust/ChangeLog"));
+        // dot-stuffed by the client, as the line starts with a dot
+        assert!(patch.contains("\n..leading dot\n"));
+        // lines already ending with CRLF are not given a second carriage return
+        assert!(patch.contains("\n+  return 0;\n"));
+        assert!(!patch.contains('\r'));
+
+        fs::remove_dir_all(files[0].parent().unwrap()).unwrap();
+    }
+}
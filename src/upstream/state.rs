@@ -0,0 +1,171 @@
+//! State of an upstream preparation, persisted in the git directory of the work directory so that
+//! a run which stopped halfway can be continued or aborted
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+const FILE_NAME: &str = ".gerris-upstream.json";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CommitState {
+    /// The commit has not been cherry-picked yet
+    Pending,
+    /// The commit was cherry-picked and did not need any changes
+    Picked,
+    /// The commit was cherry-picked and amended with the `gccrs: ` prefix
+    Amended,
+    /// The commit could not be cherry-picked and was skipped
+    Skipped,
+    /// The commit could not be cherry-picked, and the run stopped so that it can be fixed by hand
+    Conflicted,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub hash: String,
+    pub state: CommitState,
+    /// Hash of the cherry-pick on the prepared branch, once the commit is picked or amended
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub picked: String,
+    /// Files in conflict when the commit could not be cherry-picked
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct State {
+    /// Branch checked out before the run started, restored on abort
+    pub original_branch: String,
+    /// Branch containing the prepared commits
+    pub branch: String,
+    /// Branch on which to base the pull-request
+    pub base: String,
+    pub last_upstreamed_commit: String,
+    /// GCC commit on top of which the commits are prepared
+    #[serde(default)]
+    pub gcc_base: String,
+    /// Last commit of the prepared branch when the run stopped on a conflict, to check that the
+    /// conflicting commit was cherry-picked by hand on top of it
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub head: String,
+    pub commits: Vec<Entry>,
+}
+
+/// Git directory of `work`, as `git rev-parse --git-dir` gives it: `.git` itself, or the directory
+/// it links to in linked work trees and submodules
+fn git_dir(work: &Path) -> io::Result<PathBuf> {
+    let dot_git = work.join(".git");
+    if !dot_git.is_file() {
+        return Ok(dot_git);
+    }
+
+    let link = fs::read_to_string(&dot_git)?;
+    match link.trim().strip_prefix("gitdir: ") {
+        Some(dir) => Ok(work.join(dir)),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid git directory link in `{}`", dot_git.display()),
+        )),
+    }
+}
+
+impl State {
+    pub fn path(work: &Path) -> io::Result<PathBuf> {
+        Ok(git_dir(work)?.join(FILE_NAME))
+    }
+
+    /// Load the state of the run in progress in `work`, if any
+    pub fn load(work: &Path) -> io::Result<Option<State>> {
+        match fs::read_to_string(State::path(work)?) {
+            Ok(content) => Ok(Some(serde_json::from_str(&content)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, work: &Path) -> io::Result<()> {
+        fs::write(State::path(work)?, serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn remove(work: &Path) -> io::Result<()> {
+        fs::remove_file(State::path(work)?)
+    }
+
+    pub fn set(&mut self, hash: &str, state: CommitState, files: Vec<String>) {
+        if let Some(entry) = self.commits.iter_mut().find(|entry| entry.hash == hash) {
+            entry.state = state;
+            entry.files = files;
+        }
+    }
+
+    /// Record the hash of the cherry-pick of `hash` on the prepared branch
+    pub fn set_picked(&mut self, hash: &str, picked: String) {
+        if let Some(entry) = self.commits.iter_mut().find(|entry| entry.hash == hash) {
+            entry.picked = picked;
+        }
+    }
+
+    pub fn with_state(&self, state: CommitState) -> impl Iterator<Item = &Entry> {
+        self.commits
+            .iter()
+            .filter(move |entry| entry.state == state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let work = std::env::temp_dir().join(format!("gerris-state-{}", std::process::id()));
+        fs::create_dir_all(work.join(".git")).unwrap();
+
+        assert_eq!(State::load(&work).unwrap(), None);
+
+        let mut state = State {
+            original_branch: "master".into(),
+            branch: "prepare-2023-11-06".into(),
+            base: "gcc-patch-dev".into(),
+            last_upstreamed_commit: "61b4d27 Add foo".into(),
+            gcc_base: "a1b2c3d4e5f6".into(),
+            head: "b1a2r3".into(),
+            commits: vec![Entry {
+                hash: "e2c13b1".into(),
+                state: CommitState::Pending,
+                picked: String::new(),
+                files: vec![],
+            }],
+        };
+        state.set(
+            "e2c13b1",
+            CommitState::Conflicted,
+            vec!["gcc/rust/bar.cc".into()],
+        );
+        state.save(&work).unwrap();
+
+        assert_eq!(State::load(&work).unwrap(), Some(state));
+        assert!(work.join(".git").join(FILE_NAME).is_file());
+
+        State::remove(&work).unwrap();
+        fs::remove_dir_all(&work).unwrap();
+    }
+
+    #[test]
+    fn linked_work_tree() {
+        let work = std::env::temp_dir().join(format!("gerris-linked-{}", std::process::id()));
+        fs::create_dir_all(&work).unwrap();
+        fs::write(work.join(".git"), "gitdir: /gcc/.git/worktrees/gccrs\n").unwrap();
+
+        assert_eq!(
+            State::path(&work).unwrap(),
+            Path::new("/gcc/.git/worktrees/gccrs").join(FILE_NAME)
+        );
+
+        fs::remove_dir_all(&work).unwrap();
+    }
+}